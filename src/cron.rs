@@ -0,0 +1,201 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+/// A cron expression with 5 fields: minute hour day-of-month month day-of-week,
+/// every field is a bit set of the allowed values.
+#[derive(Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // day-of-month and day-of-week are OR-ed if both are not `*`
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+static MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+static WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Parse one value of a field, the names start from `min`, example: JAN => 1.
+fn parse_value(value: &str, names: &[&str], min: u32) -> Option<u32> {
+    if let Ok(v) = value.parse() {
+        return Some(v);
+    }
+    let value = value.to_uppercase();
+    names
+        .iter()
+        .position(|n| *n == value)
+        .map(|i| i as u32 + min)
+}
+
+/// Parse a field like `*`, `*/15`, `1-5`, `1,3,5` or `MON-FRI` into a bit set.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return None;
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, names, min)?,
+                parse_value(end, names, min)?,
+            )
+        } else {
+            let start = parse_value(range, names, min)?;
+            // 5/10 means from 5 to the max every 10
+            if part.contains('/') {
+                (start, max)
+            } else {
+                (start, start)
+            }
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Some(bits)
+}
+
+impl Cron {
+    /// Parse the cron expression, example: `0 3 * * *` or `@daily`.
+    pub fn parse(expr: &str) -> Option<Cron> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7, &WEEKDAY_NAMES)?;
+        // both 0 and 7 are sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Some(Cron {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES)?,
+            weekdays,
+            days_any: fields[2] == "*",
+            weekdays_any: fields[4] == "*",
+        })
+    }
+    fn day_match(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.days_any, self.weekdays_any) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+    /// The first time after `time` which matches the expression,
+    /// `None` if it never matches (example: `0 0 30 2 *`).
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = time.year() + 5;
+        while t.year() <= limit {
+            if self.months & (1 << t.month()) == 0 {
+                // first day of the next month
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_match(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+    }
+    #[test]
+    fn test_parse() {
+        assert!(Cron::parse("0 3 * * *").is_some());
+        assert!(Cron::parse("*/15 9-17 * * MON-FRI").is_some());
+        assert!(Cron::parse("0 0 1,15 jan,jul *").is_some());
+        assert_eq!(Cron::parse("@daily"), Cron::parse("0 0 * * *"));
+        assert_eq!(Cron::parse("0 0 * * 7"), Cron::parse("0 0 * * 0"));
+        assert!(Cron::parse("0 3 * *").is_none());
+        assert!(Cron::parse("60 3 * * *").is_none());
+        assert!(Cron::parse("0 3 0 * *").is_none());
+        assert!(Cron::parse("*/0 * * * *").is_none());
+        assert!(Cron::parse("5-1 * * * *").is_none());
+        assert!(Cron::parse("0 3 * * FOO").is_none());
+    }
+    #[test]
+    fn test_next_after() {
+        let daily = Cron::parse("0 3 * * *").unwrap();
+        assert_eq!(
+            daily.next_after(at(2026, 10, 18, 2, 59)),
+            Some(at(2026, 10, 18, 3, 0))
+        );
+        assert_eq!(
+            daily.next_after(at(2026, 10, 18, 3, 0)),
+            Some(at(2026, 10, 19, 3, 0))
+        );
+        let work = Cron::parse("*/15 9-17 * * MON-FRI").unwrap();
+        // 2026-10-17 is a saturday
+        assert_eq!(
+            work.next_after(at(2026, 10, 17, 12, 0)),
+            Some(at(2026, 10, 19, 9, 0))
+        );
+        assert_eq!(
+            work.next_after(at(2026, 10, 19, 9, 7)),
+            Some(at(2026, 10, 19, 9, 15))
+        );
+        let yearly = Cron::parse("30 12 31 dec *").unwrap();
+        assert_eq!(
+            yearly.next_after(at(2026, 10, 18, 0, 0)),
+            Some(at(2026, 12, 31, 12, 30))
+        );
+        // day-of-month or day-of-week: the 1st or every sunday
+        let either = Cron::parse("0 0 1 * SUN").unwrap();
+        assert_eq!(
+            either.next_after(at(2026, 10, 18, 0, 0)),
+            Some(at(2026, 10, 25, 0, 0))
+        );
+        assert_eq!(
+            either.next_after(at(2026, 10, 25, 0, 0)),
+            Some(at(2026, 11, 1, 0, 0))
+        );
+        let never = Cron::parse("0 0 30 2 *").unwrap();
+        assert_eq!(never.next_after(at(2026, 10, 18, 0, 0)), None);
+    }
+}
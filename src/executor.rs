@@ -1,6 +1,12 @@
-use anyhow::Result;
-use std::os::unix::process::ExitStatusExt;
-use std::process::Command;
+use anyhow::{bail, Result};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+
+use crate::sqlitedb::Commands;
 
 enum CommandType {
     Unsupport,
@@ -14,33 +20,21 @@ pub enum ExecutorExitCode {
     Success,
     Error,
     Cancel,
-    Unknown,
 }
 
-fn command_judge(command: &str) -> CommandType {
-    let cs: Vec<&str> = command.split(" ").collect();
-    let mut contain_dot = false;
-    for c in cs {
-        if c.contains(".") {
-            contain_dot = true;
-        }
-
-        if c.contains(".sh") {
-            return CommandType::Shell;
-        } else if c.contains(".py") {
-            return CommandType::Python;
-        } else if c.contains(".o") | c.contains(".exe") {
-            return CommandType::Binary;
-        }
-    }
-    if contain_dot {
-        CommandType::Unsupport
-    } else {
-        CommandType::Command
+/// The type of the program by its own extension, the arguments are not looked at,
+/// example: `cat notes.py` is a command, not a python script.
+fn command_judge(file: &str) -> CommandType {
+    match Path::new(file).extension().and_then(|e| e.to_str()) {
+        Some("sh") => CommandType::Shell,
+        Some("py") => CommandType::Python,
+        Some("o") | Some("exe") => CommandType::Binary,
+        Some(_) => CommandType::Unsupport,
+        None => CommandType::Command,
     }
 }
 
-fn get_exec_path(file_extension: &str) -> String {
+pub fn get_exec_path(file_extension: &str) -> String {
     let linux_command = Command::new("which")
         .arg(file_extension)
         .output()
@@ -50,83 +44,206 @@ fn get_exec_path(file_extension: &str) -> String {
 }
 
 pub struct Executor {
-    command: String,
+    id: i32,
+    argv: Vec<String>,
+    mode: String,
     executor: String,
+    cwd: Option<String>,
+    env_clear: bool,
+    env: Vec<(String, String)>,
 }
 
 impl Executor {
-    pub fn new(command: &str, executor: &str) -> Executor {
-        let command = command.to_string();
-        let executor = executor.to_string();
-        Executor { command, executor }
+    pub fn new(c: &Commands, env: Vec<(String, String)>) -> Executor {
+        Executor {
+            id: c.id,
+            argv: c.argv.to_vec(),
+            mode: c.mode.to_string(),
+            executor: c.executor.to_string(),
+            cwd: c.cwd.clone(),
+            env_clear: c.env_clear,
+            env,
+        }
     }
-    pub fn exec(&self) -> Result<ExecutorExitCode> {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+    /// The program and the arguments which run the job, the executor is resolved here.
+    pub fn resolve(&self) -> Result<Vec<String>> {
         //           executor        file    parameters
         // example: /usr/bin/python3 test.py -a 1
 
-        let command = &self.command;
+        if self.argv.is_empty() {
+            bail!("empty command");
+        }
+        if self.mode == "shell" {
+            // shell mode: /bin/bash -c "make && ./run.sh > out.txt"
+            return Ok(vec![
+                self.executor.to_string(),
+                String::from("-c"),
+                self.argv[0].to_string(),
+            ]);
+        }
+        let file = &self.argv[0];
         let executor = if self.executor == "null" {
-            let command_type = command_judge(command);
+            let command_type = command_judge(file);
             match command_type {
                 CommandType::Shell => get_exec_path("bash"),
                 CommandType::Python => get_exec_path("python3"),
-                CommandType::Command | CommandType::Binary => command.to_string(),
-                CommandType::Unsupport => command.to_string(),
+                CommandType::Command | CommandType::Binary => file.to_string(),
+                CommandType::Unsupport => file.to_string(),
             }
         } else {
             self.executor.to_string()
         };
-
-        let mut command_split: Vec<&str> = command.split(" ").collect();
-        let status = if command_split.len() > 0 {
-            let file = command_split[0];
-            let mut args = Vec::new();
-            for c in &mut command_split[1..] {
-                args.push(c.to_string());
-            }
-            let status = if executor != file {
-                println!(">>> Run: {} {}", executor, command);
-                Command::new(executor).arg(file).args(args).status()?
-            } else {
-                println!(">>> Run: {}", command);
-                Command::new(file).args(args).status()?
-            };
-            Some(status)
+        let mut program = Vec::new();
+        if &executor != file {
+            program.push(executor);
+        }
+        program.extend(self.argv.iter().cloned());
+        Ok(program)
+    }
+    /// Build the process of the job.
+    fn build(&self) -> Result<Command> {
+        let program = self.resolve()?;
+        if self.mode == "shell" {
+            println!(
+                ">>> Run [{}]: {} -c {}",
+                self.id, self.executor, self.argv[0]
+            );
         } else {
-            None
-        };
-
-        let ret = match status {
-            Some(status) => {
-                if status.success() {
-                    println!("<<<");
-                    ExecutorExitCode::Success
-                } else {
-                    // exit status: 1 => program error
-                    // signal: 2 (SIGINT) => user ctrl-c
-                    let status_code = match status.code() {
-                        Some(s) => s,
-                        _ => match status.signal() {
-                            Some(s) => s,
-                            _ => 0,
-                        },
-                    };
-                    if status_code == 1 {
-                        println!("<<< Error");
-                        ExecutorExitCode::Error
-                    } else if status_code == 2 {
-                        ExecutorExitCode::Cancel
-                    } else {
-                        ExecutorExitCode::Unknown
-                    }
-                }
+            let command = shlex::try_join(self.argv.iter().map(|a| a.as_str()))?;
+            if program.len() > self.argv.len() {
+                println!(">>> Run [{}]: {} {}", self.id, program[0], command);
+            } else {
+                println!(">>> Run [{}]: {}", self.id, command);
             }
-            _ => {
-                println!("<<<");
-                ExecutorExitCode::Unknown
+        }
+        let mut process = Command::new(&program[0]);
+        process.args(&program[1..]);
+        Ok(process)
+    }
+    /// Start the job in a child process and return without waiting for it,
+    /// the stdout and stderr of the job are written into the log files.
+    pub fn spawn(&self, stdout_log: &Path, stderr_log: &Path) -> Result<Child> {
+        let mut process = self.build()?;
+        if let Some(cwd) = &self.cwd {
+            process.current_dir(cwd);
+        }
+        // replay the environment saved when the job was added
+        if self.env_clear {
+            process.env_clear();
+        }
+        process.envs(self.env.iter().map(|(k, v)| (k, v)));
+        // the job and its children get their own process group,
+        // so `lucq kill` can signal all of them
+        let child = process
+            .process_group(0)
+            .stdout(File::create(stdout_log)?)
+            .stderr(File::create(stderr_log)?)
+            .spawn()?;
+        Ok(child)
+    }
+    /// Convert the exit status of a reaped child into the job result,
+    /// any non-zero exit code or signal counts as a failure.
+    pub fn exit_code(&self, status: ExitStatus) -> ExecutorExitCode {
+        if status.success() {
+            println!("<<< [{}]", self.id);
+            ExecutorExitCode::Success
+        } else if let Some(code) = status.code() {
+            println!("<<< [{}] Error (exit {})", self.id, code);
+            ExecutorExitCode::Error
+        } else {
+            // signal: 2 (SIGINT) => user ctrl-c
+            let signal = status.signal().unwrap_or_default();
+            println!("<<< [{}] Error ({})", self.id, signal_name(signal));
+            if signal == libc::SIGINT {
+                ExecutorExitCode::Cancel
+            } else {
+                ExecutorExitCode::Error
             }
-        };
+        }
+    }
+}
+
+/// Resource usage of a finished job.
+pub struct Rusage {
+    pub user_time: f64,   // seconds
+    pub system_time: f64, // seconds
+    pub max_rss: i64,     // KiB
+}
+
+fn timeval_secs(time: libc::timeval) -> f64 {
+    time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0
+}
+
+/// Like `Child::try_wait`, but also return the resource usage of the job (`wait4`).
+pub fn try_wait_rusage(child: &Child) -> io::Result<Option<(ExitStatus, Rusage)>> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    let pid = unsafe { libc::wait4(child.id() as i32, &mut status, libc::WNOHANG, &mut usage) };
+    match pid {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ => Ok(Some((
+            ExitStatus::from_raw(status),
+            Rusage {
+                user_time: timeval_secs(usage.ru_utime),
+                system_time: timeval_secs(usage.ru_stime),
+                max_rss: usage.ru_maxrss,
+            },
+        ))),
+    }
+}
+
+/// Send the signal to every process in the group, false if the group not exists.
+pub fn signal_group(pgid: i32, signal: i32) -> bool {
+    unsafe { libc::kill(-pgid, signal) == 0 }
+}
+
+pub fn group_alive(pgid: i32) -> bool {
+    signal_group(pgid, 0)
+}
+
+/// The common linux signals.
+static SIGNALS: [(i32, &str); 18] = [
+    (1, "SIGHUP"),
+    (2, "SIGINT"),
+    (3, "SIGQUIT"),
+    (4, "SIGILL"),
+    (6, "SIGABRT"),
+    (7, "SIGBUS"),
+    (8, "SIGFPE"),
+    (9, "SIGKILL"),
+    (10, "SIGUSR1"),
+    (11, "SIGSEGV"),
+    (12, "SIGUSR2"),
+    (13, "SIGPIPE"),
+    (14, "SIGALRM"),
+    (15, "SIGTERM"),
+    (18, "SIGCONT"),
+    (19, "SIGSTOP"),
+    (24, "SIGXCPU"),
+    (25, "SIGXFSZ"),
+];
+
+/// Name of the common linux signals, example: 9 => SIGKILL.
+pub fn signal_name(signal: i32) -> String {
+    match SIGNALS.iter().find(|(n, _)| *n == signal) {
+        Some((_, name)) => name.to_string(),
+        None => format!("signal {}", signal),
+    }
+}
 
-        Ok(ret)
+/// Number of the signal, example: TERM, SIGTERM or 15 => 15.
+pub fn signal_number(name: &str) -> Option<i32> {
+    if let Ok(n) = name.parse() {
+        return Some(n);
     }
+    let name = name.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(_, n)| n[3..] == *name)
+        .map(|(n, _)| *n)
 }
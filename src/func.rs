@@ -1,17 +1,27 @@
 use anyhow::Result;
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use home::home_dir;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::env;
-use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, ExitStatus};
+use std::str::FromStr;
 use std::{thread, time};
 
-use crate::executor::{Executor, ExecutorExitCode};
-use crate::sqlitedb::Commands;
+use crate::cron::Cron;
+use crate::executor::{
+    get_exec_path, group_alive, signal_group, signal_name, signal_number, try_wait_rusage,
+    Executor, ExecutorExitCode,
+};
 use crate::sqlitedb::SqliteDB;
+use crate::sqlitedb::{Attempts, Commands, Executors, JobStatus, MoveTo, Schedules};
 use crate::SQLITE_DB;
-use crate::USER_QUIT_OP;
+use crate::{RUNNING_GROUPS, USER_QUIT_OP};
 
 fn get_username() -> String {
     let linux_command = Command::new("whoami")
@@ -21,153 +31,699 @@ fn get_username() -> String {
     user.trim().to_string()
 }
 
-pub fn add(command: &str, executor: &str, before: i32, after: i32) -> Result<()> {
-    let add_time = Utc::now().timestamp();
-    let db = SqliteDB::new()?;
-    let user = get_username();
-    let command = if command.contains(".") {
-        let command_split: Vec<&str> = command.split(" ").collect();
-        let (script_file, new_command) = if command_split[0] != "python3"
-            && command_split[0] != "python"
-            && command_split[0] != "bash"
-            && command_split[0] != "zsh"
-            && command_split[0] != "fish"
-        {
-            (command_split[0], command.to_string())
-        } else {
-            if command_split.len() > 0 {
-                (command_split[1], command_split[1..].join(" "))
-            } else {
-                panic!("wrong command: {}", command);
-            }
-        };
-        let path = Path::new(script_file);
+/// Directory of the job log files, `$XDG_DATA_HOME/lucq/logs` or `~/.local/share/lucq/logs`.
+fn log_dir() -> PathBuf {
+    let data_dir = match env::var("XDG_DATA_HOME") {
+        Ok(d) if !d.is_empty() => PathBuf::from(d),
+        _ => home_dir().unwrap().join(".local").join("share"),
+    };
+    data_dir.join("lucq").join("logs")
+}
+
+/// Remove the log files of the job and of all its attempts.
+fn remove_logs(db: &SqliteDB, c: &Commands) -> Result<()> {
+    let mut logs = vec![c.stdout_log.clone(), c.stderr_log.clone()];
+    for a in db.select_attempts(c.id)? {
+        logs.push(a.stdout_log);
+        logs.push(a.stderr_log);
+    }
+    for log in logs.into_iter().flatten() {
+        let _ = fs::remove_file(log);
+    }
+    Ok(())
+}
+
+/// The shell used by the shell mode jobs: `lucq config shell`, $SHELL or /bin/sh.
+fn default_shell(db: &SqliteDB) -> Result<String> {
+    if let Some(shell) = db.get_setting("shell")? {
+        return Ok(shell);
+    }
+    match env::var("SHELL") {
+        Ok(shell) if !shell.is_empty() => Ok(shell),
+        _ => Ok(String::from("/bin/sh")),
+    }
+}
+
+/// Split the command into argv, `None` if the command is wrong.
+fn command_to_job(command: &str, executor: &str, user: &str, add_time: i64) -> Option<Commands> {
+    // split the command with the shell quoting rules, example: --name "my run"
+    let mut argv = match shlex::split(command) {
+        Some(argv) if !argv.is_empty() => argv,
+        _ => {
+            println!("Wrong command: {}", command);
+            return None;
+        }
+    };
+    // the argv is kept as typed, only a script file run directly gets its full path,
+    // example: ./train.py, the interpreter is chosen by its extension
+    if argv[0].contains(".") {
+        let path = Path::new(&argv[0]);
         if path.exists() {
             let current_dir = env::current_dir().unwrap();
-            format!("{}/{}", current_dir.display(), new_command)
+            argv[0] = current_dir.join(path).to_string_lossy().to_string();
         } else {
             println!("Warning !!!");
-            println!("File [{}] not exists!", script_file);
-            return Ok(());
+            println!("File [{}] not exists!", argv[0]);
+            return None;
         }
+    }
+    Some(Commands::new(user, argv, executor, add_time))
+}
+
+/// The job of the command added in the current directory, `None` if the command is wrong.
+fn new_job(
+    db: &SqliteDB,
+    command: &str,
+    executor: Option<&str>,
+    shell: bool,
+    user: &str,
+    add_time: i64,
+) -> Result<Option<Commands>> {
+    let mut cm = if shell {
+        // keep the whole command as a script for `$SHELL -c`
+        let shell = match executor {
+            Some(executor) => executor.to_string(),
+            None => default_shell(db)?,
+        };
+        let mut cm = Commands::new(user, vec![command.to_string()], &shell, add_time);
+        cm.command = command.to_string();
+        cm.mode = String::from("shell");
+        cm
     } else {
-        command.to_string()
+        match command_to_job(command, executor.unwrap_or("null"), user, add_time) {
+            Some(cm) => cm,
+            None => return Ok(None),
+        }
     };
-    if before == -1 && after == -1 {
-        db.insert(&user, &command, &executor, add_time)?;
-    } else if before != -1 && after == -1 {
-        let commands = db.select_after(before - 1)?;
-        let mut id_vec = Vec::new();
-        for c in commands {
-            id_vec.push(c.id);
-        }
-        db.move_jobs(&id_vec)?;
-        db.insert_with_id(before, &user, &command, &executor, add_time)?;
-    } else if before == -1 && after != -1 {
-        let commands = db.select_after(after)?;
-        let mut id_vec = Vec::new();
-        for c in commands {
-            id_vec.push(c.id);
-        }
-        db.move_jobs(&id_vec)?;
-        db.insert_with_id(after + 1, &user, &command, &executor, add_time)?;
-    } else {
-        println!("Wrong parameters!")
+    // the job runs in the directory where it was added
+    cm.cwd = Some(env::current_dir()?.to_string_lossy().to_string());
+    Ok(Some(cm))
+}
+
+/// Options of `lucq add`.
+pub struct AddOptions {
+    pub executor: Option<String>,
+    pub shell: bool,
+    pub before: Option<i32>,
+    pub after: Option<i32>,
+    pub env_all: bool,         // save the whole environment
+    pub env_keep: Vec<String>, // save the variables match the patterns, example: CUDA_*
+    pub env: Vec<String>,      // K=V
+    pub requeue_on_crash: bool,
+    pub timeout: Option<String>, // example: 2h30m
+    pub retries: u32,
+    pub retry_delay: Option<String>,   // example: 30s
+    pub retry_backoff: Option<String>, // example: 2x
+    pub after_ok: Vec<i32>,            // run after these jobs finished
+    pub after_any: Vec<i32>,           // run after these jobs done, whatever the result
+    pub after_fail: Vec<i32>,          // run after these jobs failed
+    pub priority: Option<String>,      // high, normal, low or a number
+    pub at: Option<String>,            // example: 2026-10-19 22:00 or 22:00
+    pub r#in: Option<String>,          // example: 3h
+    pub queue: String,
+}
+
+/// Parse the local time into UTC timestamp, example: 2026-10-19 22:00,
+/// a time without date (22:00) means the next time it comes.
+fn parse_start_time(at: &str, now: DateTime<Local>) -> Option<i64> {
+    let at = at.trim();
+    for format in ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(at, format) {
+            let datetime = Local.from_local_datetime(&datetime).earliest()?;
+            return Some(datetime.timestamp());
+        }
     }
-    Ok(())
+    for format in ["%H:%M", "%H:%M:%S"] {
+        if let Ok(time) = NaiveTime::parse_from_str(at, format) {
+            let mut date = now.date_naive();
+            if time <= now.time() {
+                date = date.succ_opt()?;
+            }
+            let datetime = Local.from_local_datetime(&date.and_time(time)).earliest()?;
+            return Some(datetime.timestamp());
+        }
+    }
+    None
+}
+
+/// Parse the priority, high is 10, normal is 0 and low is -10.
+fn parse_priority(priority: &str) -> Option<i32> {
+    match priority {
+        "high" => Some(10),
+        "normal" => Some(0),
+        "low" => Some(-10),
+        _ => priority.parse().ok(),
+    }
+}
+
+pub fn priority_name(priority: i32) -> String {
+    match priority {
+        10 => String::from("high"),
+        0 => String::from("normal"),
+        -10 => String::from("low"),
+        _ => priority.to_string(),
+    }
+}
+
+/// Match the name with a pattern which support `*` and `?`.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    // position of the last `*` and the name position it matched
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
 }
 
-pub fn delete(id_str: &str) -> Result<()> {
+/// The environment saved with the job.
+fn capture_env(opts: &AddOptions) -> Option<Vec<(String, String)>> {
+    let mut env = BTreeMap::new();
+    for (k, v) in env::vars() {
+        if opts.env_all || opts.env_keep.iter().any(|p| wildcard_match(p, &k)) {
+            env.insert(k, v);
+        }
+    }
+    for kv in &opts.env {
+        match kv.split_once("=") {
+            Some((k, v)) if !k.is_empty() => {
+                env.insert(k.to_string(), v.to_string());
+            }
+            _ => {
+                println!("Wrong environment variable: {} (example: K=V)", kv);
+                return None;
+            }
+        }
+    }
+    Some(env.into_iter().collect())
+}
+
+pub fn add(command: &str, opts: &AddOptions) -> Result<()> {
+    let executor = opts.executor.as_deref();
+    let add_time = Utc::now().timestamp();
     let db = SqliteDB::new()?;
-    let mut success = true;
-    if id_str.contains("-") {
-        let id_split: Vec<&str> = id_str.split("-").collect();
-        if id_split.len() == 2 {
-            let start: i32 = id_split[0].parse().unwrap();
-            let end: i32 = id_split[1].parse().unwrap();
-            if start < end {
-                for id in start..=end {
-                    db.remove_by_id(id)?;
+    let user = get_username();
+    let env = match capture_env(opts) {
+        Some(env) => env,
+        None => return Ok(()),
+    };
+    let mut cm = match new_job(&db, command, executor, opts.shell, &user, add_time)? {
+        Some(cm) => cm,
+        None => return Ok(()),
+    };
+    cm.env_clear = opts.env_all;
+    cm.requeue_on_crash = opts.requeue_on_crash;
+    if let Some(timeout) = &opts.timeout {
+        match parse_duration(timeout) {
+            Some(timeout) if timeout > 0 => cm.timeout = Some(timeout),
+            _ => {
+                println!("Wrong timeout: {} (example: 2h30m)", timeout);
+                return Ok(());
+            }
+        }
+    }
+    cm.retries = opts.retries as i32;
+    if let Some(delay) = &opts.retry_delay {
+        match parse_duration(delay) {
+            Some(delay) if delay >= 0 => cm.retry_delay = delay,
+            _ => {
+                println!("Wrong retry delay: {} (example: 30s)", delay);
+                return Ok(());
+            }
+        }
+    }
+    if let Some(backoff) = &opts.retry_backoff {
+        match parse_backoff(backoff) {
+            Some(backoff) => cm.retry_backoff = backoff,
+            None => {
+                println!("Wrong retry backoff: {} (example: 2x)", backoff);
+                return Ok(());
+            }
+        }
+    }
+    let mut deps = Vec::new();
+    for (ids, kind) in [
+        (&opts.after_ok, "ok"),
+        (&opts.after_any, "any"),
+        (&opts.after_fail, "fail"),
+    ] {
+        for depends_on in ids {
+            if db.select_by_id(*depends_on)?.is_none() {
+                println!("Job [{}] not exists!", depends_on);
+                return Ok(());
+            }
+            deps.push((*depends_on, kind.to_string()));
+        }
+    }
+    if let Some(priority) = &opts.priority {
+        match parse_priority(priority) {
+            Some(priority) => cm.priority = priority,
+            None => {
+                println!(
+                    "Wrong priority: {} (high, normal, low or a number)",
+                    priority
+                );
+                return Ok(());
+            }
+        }
+    }
+    if let Some(at) = &opts.at {
+        match parse_start_time(at, Local::now()) {
+            Some(at) => cm.not_before = Some(at),
+            None => {
+                println!(
+                    "Wrong start time: {} (example: \"2026-10-19 22:00\" or 22:00)",
+                    at
+                );
+                return Ok(());
+            }
+        }
+    }
+    if let Some(delay) = &opts.r#in {
+        // the start time must be a date which can be shown
+        let not_before = parse_duration(delay)
+            .filter(|delay| *delay >= 0)
+            .and_then(|delay| add_time.checked_add(delay))
+            .filter(|t| DateTime::from_timestamp(*t, 0).is_some());
+        match not_before {
+            Some(not_before) => cm.not_before = Some(not_before),
+            None => {
+                println!("Wrong delay: {} (example: 3h)", delay);
+                return Ok(());
+            }
+        }
+    }
+    if !valid_queue_name(&opts.queue) {
+        println!("Wrong queue name: {}", opts.queue);
+        return Ok(());
+    }
+    cm.queue = opts.queue.clone();
+    // the ids never change, only the position of the job is chosen,
+    // no other job can take the position before the insert
+    let tx = db.transaction()?;
+    let (position, target) = match (opts.before, opts.after) {
+        (Some(before), _) => (db.position_next_to(before, true, None)?, before),
+        (None, Some(after)) => (db.position_next_to(after, false, None)?, after),
+        (None, None) => (Some(db.next_position()?), 0),
+    };
+    cm.position = match position {
+        Some(position) => position,
+        None => {
+            println!("Job [{}] not exists!", target);
+            return Ok(());
+        }
+    };
+    let id = db.insert(&cm)?;
+    db.insert_env(id, &env)?;
+    db.insert_dependencies(id, &deps)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Job ids from the command line, example: 1, 1-5 or 1,3,5-7.
+#[derive(Clone, Debug)]
+pub struct Ids(pub Vec<i32>);
+
+impl FromStr for Ids {
+    type Err = String;
+    fn from_str(ids: &str) -> Result<Ids, String> {
+        let wrong = || format!("wrong id(s): {} (example: 1, 1-5 or 1,3,5-7)", ids);
+        let mut ret = Vec::new();
+        for part in ids.split(',') {
+            match part.trim().split_once('-') {
+                Some((start, end)) => {
+                    let start: i32 = start.trim().parse().map_err(|_| wrong())?;
+                    let end: i32 = end.trim().parse().map_err(|_| wrong())?;
+                    if start > end {
+                        return Err(wrong());
+                    }
+                    ret.extend(start..=end);
                 }
-            } else {
-                success = false;
+                None => ret.push(part.trim().parse().map_err(|_| wrong())?),
             }
-        } else {
-            success = false;
         }
-    } else {
-        let id: i32 = id_str.parse().unwrap();
-        db.remove_by_id(id)?;
+        Ok(Ids(ret))
+    }
+}
+
+/// Delete the job and its logs, the message tells what happened.
+pub fn delete_one(db: &SqliteDB, id: i32) -> Result<String> {
+    match db.select_by_id(id)? {
+        Some(c) => {
+            remove_logs(db, &c)?;
+            db.remove_by_id(id)?;
+            Ok(format!("Job [{}] deleted", id))
+        }
+        None => Ok(format!("Job [{}] not exists!", id)),
     }
+}
 
-    if success == false {
-        println!("Please use a-b format!");
+pub fn delete(ids: &[i32]) -> Result<()> {
+    let db = SqliteDB::new()?;
+    for id in ids {
+        println!("{}", delete_one(&db, *id)?);
     }
     Ok(())
 }
 
-pub fn cancel(id_str: &str) -> Result<()> {
+/// Cancel the waiting job, it is kept in the history but not run.
+pub fn cancel_one(db: &SqliteDB, id: i32) -> Result<String> {
+    let msg = match db.select_by_id(id)? {
+        Some(c) => match c.status {
+            JobStatus::Waiting => {
+                db.update_status(id, JobStatus::Cancelled)?;
+                format!("Job [{}] cancelled", id)
+            }
+            JobStatus::Running | JobStatus::Paused => {
+                format!(
+                    "Job [{}] is running, use `lucq kill {}` to stop it!",
+                    id, id
+                )
+            }
+            _ => format!("Job [{}] is not waiting!", id),
+        },
+        None => format!("Job [{}] not exists!", id),
+    };
+    Ok(msg)
+}
+
+pub fn cancel(ids: &[i32]) -> Result<()> {
     let db = SqliteDB::new()?;
-    let mut success = true;
-    if id_str.contains("-") {
-        let id_split: Vec<&str> = id_str.split("-").collect();
-        if id_split.len() == 2 {
-            let start: i32 = id_split[0].parse().unwrap();
-            let end: i32 = id_split[1].parse().unwrap();
-            if start < end {
-                for id in start..=end {
-                    db.update_status_cancel(id)?;
+    for id in ids {
+        println!("{}", cancel_one(&db, *id)?);
+    }
+    Ok(())
+}
+
+/// Put a finished job back into the queue, the logs and the attempts are kept,
+/// the jobs skipped because of it wait again.
+pub fn retry_one(db: &SqliteDB, id: i32) -> Result<String> {
+    let msg = match db.select_by_id(id)? {
+        Some(c) => match c.status {
+            JobStatus::Waiting | JobStatus::Running | JobStatus::Paused => {
+                format!("Job [{}] is not finished!", id)
+            }
+            _ => {
+                db.retry(id)?;
+                let unskipped = db.unskip_dependents(id)?;
+                if unskipped.is_empty() {
+                    format!("Job [{}] is waiting again", id)
+                } else {
+                    format!(
+                        "Job [{}] is waiting again, with the skipped job(s) {:?}",
+                        id, unskipped
+                    )
                 }
-            } else {
-                success = false;
             }
-        } else {
-            success = false;
+        },
+        None => format!("Job [{}] not exists!", id),
+    };
+    Ok(msg)
+}
+
+pub fn retry(id: i32) -> Result<()> {
+    let db = SqliteDB::new()?;
+    println!("{}", retry_one(&db, id)?);
+    Ok(())
+}
+
+/// The process group of a running job on this machine,
+/// the error message if the job can not be signaled.
+fn running_group(db: &SqliteDB, id: i32) -> Result<Result<i32, String>> {
+    let c = match db.select_by_id(id)? {
+        Some(c) => c,
+        None => return Ok(Err(format!("Job [{}] not exists!", id))),
+    };
+    if c.status != JobStatus::Running && c.status != JobStatus::Paused {
+        return Ok(Err(format!("Job [{}] is not running!", id)));
+    }
+    if let Some(host) = &c.owner_host {
+        if *host != hostname() {
+            return Ok(Err(format!("Job [{}] is running on {}!", id, host)));
         }
+    }
+    match c.pgid {
+        Some(pgid) => Ok(Ok(pgid)),
+        None => Ok(Err(format!("Job [{}] has no process yet!", id))),
+    }
+}
+
+/// Stop the job with SIGSTOP, the job keeps its memory and continues after `lucq resume`.
+pub fn pause_one(db: &SqliteDB, id: i32) -> Result<String> {
+    let pgid = match running_group(db, id)? {
+        Ok(pgid) => pgid,
+        Err(msg) => return Ok(msg),
+    };
+    if signal_group(pgid, libc::SIGSTOP) && db.update_running_status(id, JobStatus::Paused)? {
+        Ok(format!("Job [{}] paused", id))
     } else {
-        let id: i32 = id_str.parse().unwrap();
-        db.update_status_cancel(id)?;
+        Ok(format!("Job [{}] has exited!", id))
     }
+}
 
-    if success == false {
-        println!("Please use a-b format!");
+pub fn pause(id: i32) -> Result<()> {
+    let db = SqliteDB::new()?;
+    println!("{}", pause_one(&db, id)?);
+    Ok(())
+}
+
+pub fn resume_one(db: &SqliteDB, id: i32) -> Result<String> {
+    let pgid = match running_group(db, id)? {
+        Ok(pgid) => pgid,
+        Err(msg) => return Ok(msg),
+    };
+    if signal_group(pgid, libc::SIGCONT) && db.update_running_status(id, JobStatus::Running)? {
+        Ok(format!("Job [{}] resumed", id))
+    } else {
+        Ok(format!("Job [{}] has exited!", id))
     }
+}
+
+pub fn resume(id: i32) -> Result<()> {
+    let db = SqliteDB::new()?;
+    println!("{}", resume_one(&db, id)?);
     Ok(())
 }
 
-fn commands_show(rets: Vec<Commands>, noemoji: bool) {
-    for r in rets {
-        // status
-        let status = if noemoji {
-            if r.status == 0 {
-                " x" // waitting
-            } else if r.status == 1 {
-                " o" // finish
-            } else if r.status == 2 {
-                " e" // error
-            } else if r.status == 3 {
-                " c" // cancel
-            } else {
-                " r" // running
+/// The queue of the jobs added without `--queue`.
+pub const DEFAULT_QUEUE: &str = "default";
+
+/// The queue name is used in the lock file name, only letters, digits, `-` and `_`.
+fn valid_queue_name(queue: &str) -> bool {
+    !queue.is_empty()
+        && queue
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// All the queues are paused by `lucq queue pause`, one queue by `lucq queue pause <name>`.
+fn queue_paused(db: &SqliteDB, queue: &str) -> Result<bool> {
+    Ok(db.get_setting("queue_paused")?.is_some()
+        || db
+            .get_setting(&format!("queue_paused.{}", queue))?
+            .is_some())
+}
+
+/// Max number of the running jobs in the queue, `None` if no limit.
+fn queue_slots(db: &SqliteDB, queue: &str) -> Result<Option<i64>> {
+    match db.get_setting(&format!("queue_slots.{}", queue))? {
+        Some(slots) => Ok(slots.parse().ok()),
+        None => Ok(None),
+    }
+}
+
+/// Stop or continue starting new jobs, the running jobs are not affected.
+/// Pause or resume one queue, or all the queues if no name.
+pub fn queue_pause(name: Option<&str>, pause: bool) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let key = match name {
+        Some(name) if !valid_queue_name(name) => {
+            println!("Wrong queue name: {}", name);
+            return Ok(());
+        }
+        Some(name) => format!("queue_paused.{}", name),
+        None => String::from("queue_paused"),
+    };
+    if pause {
+        db.set_setting(&key, "1")?;
+    } else {
+        db.remove_setting(&key)?;
+        // resume all the queues
+        if name.is_none() {
+            for (k, _) in db.select_settings()? {
+                if k.starts_with("queue_paused.") {
+                    db.remove_setting(&k)?;
+                }
             }
+        }
+    }
+    queue_show(&db)
+}
+
+/// Limit the number of the running jobs in the queue, 0 means no limit.
+pub fn queue_set_slots(name: &str, slots: u32) -> Result<()> {
+    if !valid_queue_name(name) {
+        println!("Wrong queue name: {}", name);
+        return Ok(());
+    }
+    let db = SqliteDB::new()?;
+    let key = format!("queue_slots.{}", name);
+    if slots == 0 {
+        db.remove_setting(&key)?;
+    } else {
+        db.set_setting(&key, &slots.to_string())?;
+    }
+    queue_show(&db)
+}
+
+/// Show the state of every queue.
+pub fn queue() -> Result<()> {
+    let db = SqliteDB::new()?;
+    queue_show(&db)
+}
+
+fn queue_show(db: &SqliteDB) -> Result<()> {
+    let mut queues = db.select_queues()?;
+    // the queues with settings but no job yet
+    for (k, _) in db.select_settings()? {
+        let name = k
+            .strip_prefix("queue_paused.")
+            .or(k.strip_prefix("queue_slots."));
+        if let Some(name) = name {
+            queues.push(name.to_string());
+        }
+    }
+    if !queues.iter().any(|q| q == DEFAULT_QUEUE) {
+        queues.push(DEFAULT_QUEUE.to_string());
+    }
+    queues.sort();
+    queues.dedup();
+    let all = db.select_all()?;
+    for queue in queues {
+        let waiting = all
+            .iter()
+            .filter(|c| c.queue == queue && c.status == JobStatus::Waiting)
+            .count();
+        let running = db.count_running(&queue)?;
+        let slots = match queue_slots(db, &queue)? {
+            Some(slots) => slots.to_string(),
+            None => String::from("-"),
+        };
+        let state = if queue_paused(db, &queue)? {
+            "paused"
         } else {
-            if r.status == 0 {
-                // "x" // waitting
-                "😐"
-            } else if r.status == 1 {
-                // "o" // finish
-                "😁"
-            } else if r.status == 2 {
-                // "e" // error
-                "😨"
-            } else if r.status == 3 {
-                // "c" // cancel
-                "🤡"
-            } else {
-                // "r" // running
-                "🥵"
-            }
+            "running"
         };
+        println!(
+            "{} | {} | waiting {} | running {}/{}",
+            queue, state, waiting, running, slots
+        );
+    }
+    Ok(())
+}
+
+/// Send SIGTERM to the running job, the process group is returned
+/// to send SIGKILL later if the job is still alive.
+pub fn kill_one(db: &SqliteDB, id: i32) -> Result<Result<i32, String>> {
+    let pgid = match running_group(db, id)? {
+        Ok(pgid) => pgid,
+        Err(msg) => return Ok(Err(msg)),
+    };
+    // the job keeps running until the executor reaps it and writes `Killed`
+    db.request_kill(id, JobStatus::Killed)?;
+    signal_group(pgid, libc::SIGTERM);
+    // a stopped process handles SIGTERM only after it continues
+    signal_group(pgid, libc::SIGCONT);
+    Ok(Ok(pgid))
+}
+
+/// Stop a running job: SIGTERM to the process group, then SIGKILL after the grace period.
+pub fn kill(id: i32, grace: u64) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let pgid = match kill_one(&db, id)? {
+        Ok(pgid) => pgid,
+        Err(msg) => {
+            println!("{}", msg);
+            return Ok(());
+        }
+    };
+    println!("Send SIGTERM to job [{}]", id);
+
+    if !wait_group(pgid, grace) {
+        signal_group(pgid, libc::SIGKILL);
+        println!("Send SIGKILL to job [{}]", id);
+    }
+    Ok(())
+}
+
+/// Wait for the process group to exit, false if it is still alive after the grace period.
+fn wait_group(pgid: i32, grace: u64) -> bool {
+    let duration = time::Duration::from_secs_f32(0.5);
+    let deadline = time::Instant::now() + time::Duration::from_secs(grace);
+    while group_alive(pgid) {
+        if time::Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(duration);
+    }
+    true
+}
+
+pub fn status_symbol(status: JobStatus, noemoji: bool) -> &'static str {
+    if noemoji {
+        match status {
+            JobStatus::Waiting => " x",
+            JobStatus::Finished => " o",
+            JobStatus::Error => " e",
+            JobStatus::Cancelled => " c",
+            JobStatus::Paused => " p",
+            JobStatus::Killed => " k",
+            JobStatus::TimedOut => " t",
+            JobStatus::Skipped => " s",
+            JobStatus::Interrupted => " i",
+            JobStatus::Running => " r",
+        }
+    } else {
+        match status {
+            JobStatus::Waiting => "😐",
+            JobStatus::Finished => "😁",
+            JobStatus::Error => "😨",
+            JobStatus::Cancelled => "🤡",
+            JobStatus::Paused => "😴",
+            JobStatus::Killed => "💀",
+            JobStatus::TimedOut => "⏰",
+            JobStatus::Skipped => "🙈",
+            JobStatus::Interrupted => "💥",
+            JobStatus::Running => "🥵",
+        }
+    }
+}
+
+/// Print the jobs, the jobs in `changed` are highlighted.
+fn commands_show(
+    out: &mut impl Write,
+    db: &SqliteDB,
+    rets: Vec<Commands>,
+    noemoji: bool,
+    changed: &[i32],
+) -> Result<()> {
+    let now = Utc::now().timestamp();
+    for r in rets {
+        let status = status_symbol(r.status, noemoji);
 
         // used time format
         let used_time = if r.start_time != -1 {
@@ -176,140 +732,1529 @@ fn commands_show(rets: Vec<Commands>, noemoji: bool) {
             } else {
                 Utc::now().timestamp() - r.start_time
             };
-
-            let seconds = used_time % 60;
-            let minutes = (used_time / 60) % 60;
-            let hours = (used_time / 60) / 60;
-
-            let convert = |input: i64| -> String {
-                let result_str = if input < 10 {
-                    format!("0{}", input)
-                } else {
-                    format!("{}", input)
-                };
-                result_str
-            };
-
-            let seconds_str = convert(seconds);
-            let minutes_str = convert(minutes);
-            let hours_str = convert(hours);
-            let used_time = format!("{}:{}:{}", hours_str, minutes_str, seconds_str);
-            used_time
+            format_duration(used_time)
         } else {
             String::from("00:00:00")
         };
 
         // add time convert
         let add_time_str = if r.add_time != -1 {
-            let add_time = DateTime::from_timestamp(r.add_time, 0)
-                .unwrap()
-                .with_timezone(&Local);
-            add_time.format("%m-%d %H:%M").to_string()
+            time_str(r.add_time, "%m-%d %H:%M")
         } else {
             String::from("00-00 00:00")
         };
 
         let start_time_str = if r.start_time != -1 {
-            let start_time = DateTime::from_timestamp(r.start_time, 0)
-                .unwrap()
-                .with_timezone(&Local);
-            start_time.format("%m-%d %H:%M").to_string()
+            time_str(r.start_time, "%m-%d %H:%M")
         } else {
             String::from("00-00 00:00")
         };
 
         let finish_time_str = if r.finish_time != -1 {
-            let finish_time = DateTime::from_timestamp(r.finish_time, 0)
-                .unwrap()
-                .with_timezone(&Local);
-            finish_time.format("%m-%d %H:%M").to_string()
+            time_str(r.finish_time, "%m-%d %H:%M")
         } else {
             String::from("00-00 00:00")
         };
 
-        if r.executor != "null" {
-            println!("{} | {} | {} | {}", status, r.id, r.command, r.executor,);
+        let line = if r.mode == "shell" {
+            format!("{} | {} | $ {} | {}", status, r.id, r.command, r.executor)
+        } else if r.executor != "null" {
+            format!("{} | {} | {} | {}", status, r.id, r.command, r.executor)
+        } else {
+            format!("{} | {} | {}", status, r.id, r.command)
+        };
+        if changed.contains(&r.id) {
+            // bold and reversed
+            writeln!(out, "\x1b[1;7m{}\x1b[0m", line)?;
         } else {
-            println!("{} | {} | {}", status, r.id, r.command,);
+            writeln!(out, "{}", line)?;
         }
-        println!(
-            "---| add({}) | start({}) | finish({}) | used({})",
-            add_time_str, start_time_str, finish_time_str, used_time
-        );
-    }
-}
+        // how the process ended
+        let exit_str = match (r.exit_code, r.signal) {
+            (Some(code), _) => format!(" | exit({})", code),
+            (None, Some(signal)) => format!(" | killed({})", signal_name(signal)),
+            (None, None) => String::new(),
+        };
+        // killed or timed out, but not reaped yet
+        let exit_str = match (r.status, r.kill_requested) {
+            (JobStatus::Running | JobStatus::Paused, Some(status)) => {
+                format!("{} | stopping({})", exit_str, status.name())
+            }
+            _ => exit_str,
+        };
+        let exit_str = match r.timeout {
+            Some(timeout) => format!("{} | timeout({})", exit_str, format_duration(timeout)),
+            None => exit_str,
+        };
+        let exit_str = if r.retries > 0 {
+            let exit_str = format!("{} | attempt({}/{})", exit_str, r.attempt, r.retries + 1);
+            match r.retry_at {
+                Some(retry_at) => format!(
+                    "{} | retry({})",
+                    exit_str,
+                    time_str(retry_at, "%m-%d %H:%M:%S")
+                ),
+                None => exit_str,
+            }
+        } else {
+            exit_str
+        };
+        // the waiting job which does not start yet, example: at(10-19 22:00, in 03:12:00)
+        let exit_str = match r.not_before {
+            Some(not_before) if r.status == JobStatus::Waiting && not_before > now => {
+                format!(
+                    "{} | at({}, in {})",
+                    exit_str,
+                    time_str(not_before, "%m-%d %H:%M"),
+                    format_duration(not_before - now)
+                )
+            }
+            _ => exit_str,
+        };
+        let exit_str = if r.queue != DEFAULT_QUEUE {
+            format!("{} | queue({})", exit_str, r.queue)
+        } else {
+            exit_str
+        };
+        let exit_str = if r.priority != 0 {
+            format!("{} | priority({})", exit_str, priority_name(r.priority))
+        } else {
+            exit_str
+        };
+        // example: after(ok:12,13 any:14)
+        let deps = db.select_dependencies(r.id)?;
+        let exit_str = if !deps.is_empty() {
+            let mut kinds: Vec<String> = Vec::new();
+            for kind in ["ok", "any", "fail"] {
+                let ids: Vec<String> = deps
+                    .iter()
+                    .filter(|(_, k)| k == kind)
+                    .map(|(id, _)| id.to_string())
+                    .collect();
+                if !ids.is_empty() {
+                    kinds.push(format!("{}:{}", kind, ids.join(",")));
+                }
+            }
+            format!("{} | after({})", exit_str, kinds.join(" "))
+        } else {
+            exit_str
+        };
 
-pub fn list(noemoji: bool) -> Result<()> {
-    let db = SqliteDB::new()?;
-    let rets = db.select_all()?;
-    // println!("S | Jobs");
-    commands_show(rets, noemoji);
+        writeln!(
+            out,
+            "---| add({}) | start({}) | finish({}) | used({}){}",
+            add_time_str, start_time_str, finish_time_str, used_time, exit_str
+        )?;
+    }
     Ok(())
 }
 
-pub fn exec() -> Result<()> {
-    let db = SqliteDB::new()?;
-    let duration = time::Duration::from_secs_f32(1.0);
-    loop {
-        let user_quit_op = *USER_QUIT_OP.lock().unwrap();
+/// Output format of `list`, `grep` and `show`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Jsonl,
+    Csv,
+}
 
-        // When user_quit_op is true,
-        // mean the user is deciding quit the program or not,
-        // so we do not run the job.
-        if user_quit_op != true {
-            let rets = db.select_not_finish()?;
-            // rets == 1 if have job, == 0 if no job
-            for r in rets {
-                let executor = Executor::new(&r.command, &r.executor);
-                db.update_status_running(r.id)?;
-                let start_time = Utc::now().timestamp();
-                db.update_start_time(r.id, start_time)?;
-                match executor.exec() {
-                    Ok(exit_code) => match exit_code {
-                        ExecutorExitCode::Success | ExecutorExitCode::Unknown => {
-                            db.update_status_finish(r.id)?
-                        }
-                        ExecutorExitCode::Error => db.update_status_error(r.id)?,
-                        ExecutorExitCode::Cancel => db.update_status_cancel(r.id)?,
-                    },
-                    Err(e) => {
-                        println!("Program error: {}", e);
-                        db.update_status_error(r.id)?;
-                    }
-                }
-                let finish_time = Utc::now().timestamp();
-                db.update_finish_time(r.id, finish_time)?;
-            }
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Format, String> {
+        match format {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "wrong format: {} (table, json, jsonl or csv)",
+                format
+            )),
         }
-        thread::sleep(duration);
     }
 }
 
-pub fn clean() -> Result<()> {
-    let home = home_dir().unwrap();
-    let sqlite_file_path = format!("{}/{}", home.to_string_lossy(), SQLITE_DB);
-    match fs::remove_file(sqlite_file_path) {
-        _ => (),
-    }
+#[derive(Serialize)]
+struct Dependency {
+    id: i32,
+    kind: String,
+}
+
+/// A job with its dependencies, and the attempts in `show`.
+#[derive(Serialize)]
+struct JobView<'a> {
+    #[serde(flatten)]
+    job: &'a Commands,
+    dependencies: Vec<Dependency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<Vec<Attempts>>,
+}
+
+fn job_view<'a>(db: &SqliteDB, c: &'a Commands, attempts: bool) -> Result<JobView<'a>> {
+    let dependencies = db
+        .select_dependencies(c.id)?
+        .into_iter()
+        .map(|(id, kind)| Dependency { id, kind })
+        .collect();
+    let attempts = match attempts {
+        true => Some(db.select_attempts(c.id)?),
+        false => None,
+    };
+    Ok(JobView {
+        job: c,
+        dependencies,
+        attempts,
+    })
+}
+
+/// One csv cell, the arrays (argv, dependencies...) are kept as json.
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Print the jobs in the machine readable formats.
+fn commands_output(out: &mut impl Write, views: &[JobView], format: Format) -> Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, views)?;
+            writeln!(out)?;
+        }
+        Format::Jsonl => {
+            for v in views {
+                serde_json::to_writer(&mut *out, v)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            let rows: Vec<Value> = views
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<Result<_, _>>()?;
+            // the header comes from an empty job if there is no job
+            let header = match rows.first() {
+                Some(row) => row.clone(),
+                None => {
+                    let empty = Commands::new("", Vec::new(), "null", -1);
+                    serde_json::to_value(JobView {
+                        job: &empty,
+                        dependencies: Vec::new(),
+                        attempts: None,
+                    })?
+                }
+            };
+            let keys: Vec<&String> = header
+                .as_object()
+                .map(|o| o.keys().collect())
+                .unwrap_or_default();
+            writer.write_record(&keys)?;
+            for row in &rows {
+                writer.write_record(keys.iter().map(|k| csv_cell(&row[k.as_str()])))?;
+            }
+            writer.flush()?;
+        }
+        Format::Table => unreachable!("the table is printed by commands_show"),
+    }
+    Ok(())
+}
+
+/// Print the jobs in the format, the table is the human readable one.
+fn commands_print(db: &SqliteDB, rets: Vec<Commands>, format: Format, noemoji: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    if format == Format::Table {
+        return ignore_broken_pipe(commands_show(&mut out, db, rets, noemoji, &[]));
+    }
+    let views = rets
+        .iter()
+        .map(|c| job_view(db, c, false))
+        .collect::<Result<Vec<_>>>()?;
+    ignore_broken_pipe(commands_output(&mut out, &views, format))
+}
+
+/// The reader of the output is gone, example: `lucq list | head`, stop printing quietly.
+fn ignore_broken_pipe(ret: Result<()>) -> Result<()> {
+    let broken_pipe = |e: &anyhow::Error| {
+        let kind = if let Some(e) = e.downcast_ref::<io::Error>() {
+            Some(e.kind())
+        } else if let Some(e) = e.downcast_ref::<serde_json::Error>() {
+            e.io_error_kind()
+        } else if let Some(e) = e.downcast_ref::<csv::Error>() {
+            match e.kind() {
+                csv::ErrorKind::Io(e) => Some(e.kind()),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        kind == Some(io::ErrorKind::BrokenPipe)
+    };
+    match ret {
+        Err(e) if broken_pipe(&e) => Ok(()),
+        ret => ret,
+    }
+}
+
+fn select_list(db: &SqliteDB, queue: Option<&str>) -> Result<Vec<Commands>> {
+    let mut rets = db.select_all()?;
+    if let Some(queue) = queue {
+        rets.retain(|c| c.queue == queue);
+    }
+    Ok(rets)
+}
+
+/// List the jobs, only the jobs in the queue if `queue` is set.
+pub fn list(noemoji: bool, queue: Option<&str>, format: Format) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let rets = select_list(&db, queue)?;
+    // println!("S | Jobs");
+    commands_print(&db, rets, format, noemoji)?;
+    Ok(())
+}
+
+/// Redraw the list every `interval` seconds, the jobs whose status changed since
+/// the last redraw are highlighted. With `until_empty` it returns when no job
+/// is waiting or running anymore.
+pub fn list_watch(
+    noemoji: bool,
+    queue: Option<&str>,
+    interval: u64,
+    until_empty: bool,
+) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let mut last: Option<BTreeMap<i32, JobStatus>> = None;
+    loop {
+        // do not draw over the quit prompt of ctrl-c
+        if !*USER_QUIT_OP.lock().unwrap() {
+            let rets = select_list(&db, queue)?;
+            let changed: Vec<i32> = match &last {
+                Some(last) => rets
+                    .iter()
+                    .filter(|c| last.get(&c.id) != Some(&c.status))
+                    .map(|c| c.id)
+                    .collect(),
+                None => Vec::new(),
+            };
+            let empty = !rets.iter().any(|c| {
+                matches!(
+                    c.status,
+                    JobStatus::Waiting | JobStatus::Running | JobStatus::Paused
+                )
+            });
+            last = Some(rets.iter().map(|c| (c.id, c.status)).collect());
+
+            // move to the top left and clear the screen
+            print!("\x1b[H\x1b[2J");
+            println!(
+                "Every {}s: lucq list | {}",
+                interval,
+                Local::now().format("%Y-%m-%d %H:%M:%S")
+            );
+            commands_show(&mut io::stdout().lock(), &db, rets, noemoji, &changed)?;
+            io::stdout().flush()?;
+            if until_empty && empty {
+                println!(">>> The queue is empty");
+                return Ok(());
+            }
+        }
+        thread::sleep(time::Duration::from_secs(interval));
+    }
+}
+
+fn finish_job(
+    db: &SqliteDB,
+    id: i32,
+    exit_code: ExecutorExitCode,
+    status: Option<ExitStatus>,
+) -> Result<()> {
+    let c = match db.select_by_id(id)? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let job_status = match (exit_code, c.kill_requested) {
+        // killed by `lucq kill` or the timeout
+        (_, Some(status)) => status,
+        (ExecutorExitCode::Success, None) => JobStatus::Finished,
+        (ExecutorExitCode::Error, None) => JobStatus::Error,
+        (ExecutorExitCode::Cancel, None) => JobStatus::Cancelled,
+    };
+    let (code, signal) = match status {
+        Some(status) => (status.code(), status.signal()),
+        None => (None, None),
+    };
+    let finish_time = Utc::now().timestamp();
+    db.insert_attempt(&Attempts {
+        job_id: id,
+        attempt: c.attempt,
+        status: job_status,
+        start_time: c.start_time,
+        finish_time,
+        exit_code: code,
+        signal,
+        stdout_log: c.stdout_log.clone(),
+        stderr_log: c.stderr_log.clone(),
+    })?;
+    // only the failed jobs are retried, not the killed or cancelled ones
+    if job_status == JobStatus::Error && c.attempt <= c.retries {
+        let delay = retry_delay(c.retry_delay, c.retry_backoff, c.attempt);
+        println!(
+            "<<< [{}] Retry {}/{} in {}",
+            id,
+            c.attempt,
+            c.retries,
+            format_duration(delay)
+        );
+        db.retry_later(id, finish_time.saturating_add(delay))?;
+        return Ok(());
+    }
+    db.update_status(id, job_status)?;
+    db.update_exit_status(id, code, signal)?;
+    db.update_finish_time(id, finish_time)?;
+    Ok(())
+}
+
+/// Seconds to wait before the next run, the delay grows `backoff` times after every retry.
+fn retry_delay(delay: i64, backoff: f64, attempt: i32) -> i64 {
+    (delay as f64 * backoff.powi(attempt - 1)).round() as i64
+}
+
+/// Parse the retry backoff, example: 2x or 1.5.
+fn parse_backoff(backoff: &str) -> Option<f64> {
+    let backoff = backoff.trim();
+    let backoff = backoff.strip_suffix("x").unwrap_or(backoff);
+    match backoff.parse::<f64>() {
+        Ok(b) if b.is_finite() && b >= 1.0 => Some(b),
+        _ => None,
+    }
+}
+
+fn hostname() -> String {
+    match fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(h) => h.trim().to_string(),
+        Err(_) => String::from("localhost"),
+    }
+}
+
+/// Changed after every reboot.
+fn boot_id() -> String {
+    match fs::read_to_string("/proc/sys/kernel/random/boot_id") {
+        Ok(b) => b.trim().to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Is the executor which started the job still alive.
+fn owner_alive(executors: &[Executors], c: &Commands, host: &str, boot_id: &str) -> bool {
+    match (c.owner_pid, &c.owner_host, &c.owner_boot_id) {
+        (Some(pid), Some(owner_host), Some(owner_boot_id)) => {
+            let now = Utc::now().timestamp();
+            let heartbeat = executors.iter().any(|e| {
+                e.pid == pid && &e.host == owner_host && now - e.heartbeat < HEARTBEAT_TIMEOUT
+            });
+            if owner_host != host {
+                // we can not check the process on other machine, use the heartbeat instead
+                return heartbeat;
+            }
+            // this executor just started, so it can not own any job,
+            // the pid was reused by us after the reboot or the old executor died,
+            // and the pid may be reused by any process after kill -9, so the heartbeat too
+            owner_boot_id == boot_id
+                && pid != process::id() as i32
+                && Path::new(&format!("/proc/{}", pid)).exists()
+                && heartbeat
+        }
+        // started by an old version which did not save the owner
+        _ => false,
+    }
+}
+
+/// The job may outlive its crashed executor (kill -9), stop it before the job is
+/// requeued or marked as interrupted, its exit status can not be collected anymore.
+fn stop_orphan(c: &Commands, host: &str, boot_id: &str) {
+    let pgid = match c.pgid {
+        Some(pgid) => pgid,
+        None => return,
+    };
+    // the process group is only meaningful on the same machine since the same boot
+    if c.owner_host.as_deref() != Some(host)
+        || c.owner_boot_id.as_deref() != Some(boot_id)
+        || !group_alive(pgid)
+    {
+        return;
+    }
+    println!(
+        ">>> Stop [{}]: the job is still running without its executor",
+        c.id
+    );
+    signal_group(pgid, libc::SIGTERM);
+    signal_group(pgid, libc::SIGCONT);
+    if !wait_group(pgid, ORPHAN_GRACE) {
+        signal_group(pgid, libc::SIGKILL);
+    }
+}
+
+/// Find the jobs left in running state by a crashed executor (ssh drop, reboot, kill -9),
+/// mark them as interrupted or put them back into the queue.
+fn recover_jobs(db: &SqliteDB) -> Result<()> {
+    let host = hostname();
+    let boot_id = boot_id();
+    let executors = db.select_executors()?;
+    for c in db.select_running()? {
+        if owner_alive(&executors, &c, &host, &boot_id) {
+            continue;
+        }
+        stop_orphan(&c, &host, &boot_id);
+        if let Some(status) = c.kill_requested {
+            // the job was being killed when the executor was gone
+            println!(
+                ">>> {} [{}]: the executor of the job was gone",
+                status.name(),
+                c.id
+            );
+            db.update_status(c.id, status)?;
+            db.update_finish_time(c.id, Utc::now().timestamp())?;
+        } else if c.requeue_on_crash {
+            println!(">>> Requeue [{}]: the executor of the job was gone", c.id);
+            db.requeue(c.id)?;
+        } else {
+            println!(
+                ">>> Interrupted [{}]: the executor of the job was gone",
+                c.id
+            );
+            db.update_status(c.id, JobStatus::Interrupted)?;
+            db.update_finish_time(c.id, Utc::now().timestamp())?;
+        }
+    }
+    Ok(())
+}
+
+/// A job started by this executor.
+struct RunningJob {
+    executor: Executor,
+    child: Child,
+    deadline: Option<i64>, // UTC timestamp to send the next timeout signal
+    next_signal: usize,    // index in the timeout signals
+}
+
+/// The timeout of the jobs added without `--timeout`.
+fn default_timeout(db: &SqliteDB) -> Result<Option<i64>> {
+    match db.get_setting("timeout")? {
+        Some(timeout) => Ok(parse_duration(&timeout)),
+        None => Ok(None),
+    }
+}
+
+/// The signals send to a timed out job and the seconds to wait after each signal.
+fn timeout_signals(db: &SqliteDB) -> Result<Vec<(i32, i64)>> {
+    let signals = match db.get_setting("timeout_signals")? {
+        Some(signals) => signals,
+        None => String::from(DEFAULT_TIMEOUT_SIGNALS),
+    };
+    match parse_signals(&signals) {
+        Some(signals) => Ok(signals),
+        None => Ok(parse_signals(DEFAULT_TIMEOUT_SIGNALS).unwrap()),
+    }
+}
+
+const DEFAULT_TIMEOUT_SIGNALS: &str = "TERM:10s,KILL";
+
+/// Parse the signal sequence, example: INT:30s,TERM:10s,KILL.
+fn parse_signals(signals: &str) -> Option<Vec<(i32, i64)>> {
+    let mut ret = Vec::new();
+    for s in signals.split(",") {
+        let (signal, wait) = match s.trim().split_once(":") {
+            Some((signal, wait)) => (signal, parse_duration(wait)?),
+            None => (s.trim(), 0),
+        };
+        ret.push((signal_number(signal)?, wait));
+    }
+    Some(ret)
+}
+
+/// Send the next timeout signal to the job if its time is up.
+fn timeout_job(db: &SqliteDB, job: &mut RunningJob, now: i64) -> Result<()> {
+    let deadline = match job.deadline {
+        Some(deadline) if now >= deadline => deadline,
+        _ => return Ok(()),
+    };
+    let id = job.executor.id();
+    let pgid = job.child.id() as i32;
+    if job.next_signal == 0 {
+        println!(">>> Timeout [{}]", id);
+        db.request_kill(id, JobStatus::TimedOut)?;
+    }
+    let signals = timeout_signals(db)?;
+    match signals.get(job.next_signal) {
+        Some((signal, wait)) => {
+            signal_group(pgid, *signal);
+            // a paused job handles the signal only after it continues
+            signal_group(pgid, libc::SIGCONT);
+            job.next_signal += 1;
+            job.deadline = Some(deadline.max(now).saturating_add(*wait));
+        }
+        None => job.deadline = None,
+    }
+    Ok(())
+}
+
+/// Parse the duration into seconds, example: 2h30m, 1d, 90s, 45 (seconds).
+fn parse_duration(duration: &str) -> Option<i64> {
+    let duration: String = duration.chars().filter(|c| !c.is_whitespace()).collect();
+    if duration.is_empty() {
+        return None;
+    }
+    if let Ok(seconds) = duration.parse::<i64>() {
+        return Some(seconds);
+    }
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            let n: i64 = number.parse().ok()?;
+            number.clear();
+            let unit = match c {
+                'd' => 24 * 60 * 60,
+                'h' => 60 * 60,
+                'm' => 60,
+                's' => 1,
+                _ => return None,
+            };
+            // too long to be a duration
+            total = n.checked_mul(unit).and_then(|n| total.checked_add(n))?;
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+/// Format the seconds like 02:30:00.
+pub fn format_duration(duration: i64) -> String {
+    let seconds = duration % 60;
+    let minutes = (duration / 60) % 60;
+    let hours = (duration / 60) / 60;
+    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+}
+
+fn remove_group(pgid: i32) {
+    RUNNING_GROUPS.lock().unwrap().retain(|g| *g != pgid);
+}
+
+/// Seconds without heartbeat before an executor is treated as dead.
+const HEARTBEAT_TIMEOUT: i64 = 30;
+const HEARTBEAT_INTERVAL: i64 = 5;
+/// Seconds to wait before SIGKILL when stopping the job of a crashed executor.
+const ORPHAN_GRACE: u64 = 10;
+
+/// Lock the queue for this executor, `None` if another executor already has the lock.
+fn lock_queue(queue: &str) -> Result<Option<File>> {
+    let home = home_dir().unwrap();
+    // the default queue keeps the lock file of the older versions
+    let lock_file_path = if queue == DEFAULT_QUEUE {
+        format!("{}/{}.lock", home.to_string_lossy(), SQLITE_DB)
+    } else {
+        format!("{}/{}.{}.lock", home.to_string_lossy(), SQLITE_DB, queue)
+    };
+    let mut lock_file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_file_path)?;
+    match lock_file.try_lock() {
+        Ok(()) => {
+            lock_file.set_len(0)?;
+            writeln!(lock_file, "{}", process::id())?;
+            Ok(Some(lock_file))
+        }
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Serve the queues in priority order, the jobs of the first queue start first.
+pub fn exec(slots: usize, queues: &[String]) -> Result<()> {
+    if let Some(q) = queues.iter().find(|q| !valid_queue_name(q)) {
+        println!("Wrong queue name: {}", q);
+        return Ok(());
+    }
+    let db = SqliteDB::new()?;
+    // keep the locks until the executor exit
+    let mut _locks = Vec::new();
+    for queue in queues {
+        match lock_queue(queue)? {
+            Some(lock) => _locks.push(lock),
+            None => {
+                println!(
+                    "Another executor is serving queue {}, use `lucq status` to show it!",
+                    queue
+                );
+                return Ok(());
+            }
+        }
+    }
+    let host = hostname();
+    let boot_id = boot_id();
+    let pid = process::id() as i32;
+    let now = Utc::now().timestamp();
+    db.remove_stale_executors(now - HEARTBEAT_TIMEOUT)?;
+    db.register_executor(&Executors {
+        pid,
+        host: host.clone(),
+        boot_id: boot_id.clone(),
+        slots: slots as i32,
+        start_time: now,
+        heartbeat: now,
+        queues: queues.join(","),
+    })?;
+    recover_jobs(&db)?;
+    let log_dir = log_dir();
+    fs::create_dir_all(&log_dir)?;
+    let duration = time::Duration::from_secs_f32(1.0);
+    let mut running: Vec<RunningJob> = Vec::new();
+    let mut last_heartbeat = now;
+    let mut last_paused = vec![false; queues.len()];
+    loop {
+        let now = Utc::now().timestamp();
+        if now - last_heartbeat >= HEARTBEAT_INTERVAL {
+            db.update_heartbeat(pid, &host, now)?;
+            last_heartbeat = now;
+        }
+
+        // reap the jobs which have finished since the last round
+        let mut i = 0;
+        while i < running.len() {
+            match try_wait_rusage(&running[i].child) {
+                Ok(Some((status, usage))) => {
+                    let job = running.remove(i);
+                    remove_group(job.child.id() as i32);
+                    let id = job.executor.id();
+                    db.update_rusage(id, usage.user_time, usage.system_time, usage.max_rss)?;
+                    let exit_code = job.executor.exit_code(status);
+                    finish_job(&db, id, exit_code, Some(status))?;
+                }
+                Ok(None) => i += 1,
+                Err(e) => {
+                    println!("Program error: {}", e);
+                    let job = running.remove(i);
+                    remove_group(job.child.id() as i32);
+                    finish_job(&db, job.executor.id(), ExecutorExitCode::Error, None)?;
+                }
+            }
+        }
+
+        // stop the jobs which run out of time
+        for job in running.iter_mut() {
+            timeout_job(&db, job, now)?;
+        }
+
+        fire_schedules(&db, queues, now)?;
+
+        // the jobs whose dependencies will never be satisfied
+        for id in db.skip_unsatisfied()? {
+            println!(">>> Skip [{}]: dependency not satisfied", id);
+        }
+
+        let user_quit_op = *USER_QUIT_OP.lock().unwrap();
+        let mut paused = Vec::new();
+        for (i, queue) in queues.iter().enumerate() {
+            let queue_paused = queue_paused(&db, queue)?;
+            if queue_paused != last_paused[i] {
+                if queue_paused {
+                    println!(">>> Queue {} paused", queue);
+                } else {
+                    println!(">>> Queue {} resumed", queue);
+                }
+                last_paused[i] = queue_paused;
+            }
+            paused.push(queue_paused);
+        }
+        // When user_quit_op is true,
+        // mean the user is deciding quit the program or not,
+        // so we do not run new job.
+        if !user_quit_op {
+            while running.len() < slots {
+                let start_time = Utc::now().timestamp();
+                let mut claimed = None;
+                for (i, queue) in queues.iter().enumerate() {
+                    if paused[i] {
+                        continue;
+                    }
+                    if let Some(limit) = queue_slots(&db, queue)? {
+                        if db.count_running(queue)? >= limit {
+                            continue;
+                        }
+                    }
+                    claimed = db.claim_next(queue, pid, &host, &boot_id, start_time)?;
+                    if claimed.is_some() {
+                        break;
+                    }
+                }
+                let r = match claimed {
+                    Some(r) => r,
+                    None => break,
+                };
+                let executor = Executor::new(&r, db.select_env(r.id)?);
+                // keep the logs of the earlier attempts, example: 9.out, 9.2.out, 9.3.out,
+                // counted over the retries by the user too
+                let run = db.select_attempts(r.id)?.len() + 1;
+                let name = if run > 1 {
+                    format!("{}.{}", r.id, run)
+                } else {
+                    r.id.to_string()
+                };
+                let stdout_log = log_dir.join(format!("{}.out", name));
+                let stderr_log = log_dir.join(format!("{}.err", name));
+                db.update_logs(
+                    r.id,
+                    &stdout_log.to_string_lossy(),
+                    &stderr_log.to_string_lossy(),
+                )?;
+                match executor.spawn(&stdout_log, &stderr_log) {
+                    Ok(child) => {
+                        let child_pid = child.id() as i32;
+                        db.update_pid(r.id, child_pid, child_pid)?;
+                        RUNNING_GROUPS.lock().unwrap().push(child_pid);
+                        let timeout = match r.timeout {
+                            Some(timeout) => Some(timeout),
+                            None => default_timeout(&db)?,
+                        };
+                        running.push(RunningJob {
+                            executor,
+                            child,
+                            deadline: timeout.map(|t| start_time.saturating_add(t)),
+                            next_signal: 0,
+                        });
+                    }
+                    Err(e) => {
+                        println!("Program error: {}", e);
+                        finish_job(&db, r.id, ExecutorExitCode::Error, None)?;
+                    }
+                }
+            }
+        }
+        thread::sleep(duration);
+    }
+}
+
+/// Show the live executors and the number of the jobs.
+pub fn status() -> Result<()> {
+    let db = SqliteDB::new()?;
+    let now = Utc::now().timestamp();
+    let executors = db.select_executors()?;
+    let running = db.select_running()?;
+    let live: Vec<&Executors> = executors
+        .iter()
+        .filter(|e| now - e.heartbeat < HEARTBEAT_TIMEOUT)
+        .collect();
+    if live.is_empty() {
+        println!("No executor is running!");
+    }
+    for e in live {
+        let jobs = running
+            .iter()
+            .filter(|c| c.owner_pid == Some(e.pid) && c.owner_host.as_deref() == Some(&e.host))
+            .count();
+        let start_time = DateTime::from_timestamp(e.start_time, 0)
+            .unwrap()
+            .with_timezone(&Local);
+        println!(
+            "{}@{} | queues({}) | running {}/{} | start({}) | heartbeat({}s ago)",
+            e.pid,
+            e.host,
+            e.queues,
+            jobs,
+            e.slots,
+            start_time.format("%m-%d %H:%M"),
+            now - e.heartbeat
+        );
+    }
+    Ok(())
+}
+
+pub fn clean() -> Result<()> {
+    let home = home_dir().unwrap();
+    let sqlite_file_path = format!("{}/{}", home.to_string_lossy(), SQLITE_DB);
+    let _ = fs::remove_file(sqlite_file_path);
+    let _ = fs::remove_dir_all(log_dir());
     println!("Clean database finish!");
     Ok(())
 }
 
+/// Renumber the positions of the jobs, the ids are not changed.
 pub fn align() -> Result<()> {
     let db = SqliteDB::new()?;
-    // get all id
-    let ret = db.select_all()?;
-    let mut id_vec: Vec<i32> = Vec::new();
-    for r in ret {
-        id_vec.push(r.id);
+    db.align_positions()?;
+    Ok(())
+}
+
+/// Move the job in the queue, the message tells what happened.
+pub fn move_one(db: &SqliteDB, id: i32, to: MoveTo) -> Result<String> {
+    let target = match to {
+        MoveTo::Before(target) | MoveTo::After(target) => Some(target),
+        MoveTo::Top | MoveTo::Bottom => None,
+    };
+    if db.move_job(id, to)? {
+        return Ok(format!("Job [{}] moved", id));
     }
-    db.align_id(&id_vec)?;
+    match target {
+        Some(target) if db.select_by_id(id)?.is_some() => {
+            Ok(format!("Job [{}] not exists!", target))
+        }
+        _ => Ok(format!("Job [{}] not exists!", id)),
+    }
+}
+
+/// Move the job in the queue, the id of the job is not changed.
+pub fn move_job(id: i32, to: MoveTo) -> Result<()> {
+    let db = SqliteDB::new()?;
+    println!("{}", move_one(&db, id, to)?);
     Ok(())
 }
 
-pub fn grep(name: &str, noemoji: bool) -> Result<()> {
+pub fn grep(name: &str, noemoji: bool, format: Format) -> Result<()> {
     let db = SqliteDB::new()?;
     let rets = db.select_grep(name)?;
-    commands_show(rets, noemoji);
+    commands_print(&db, rets, format, noemoji)?;
+    Ok(())
+}
+
+/// Local time in the format, `-` if the time can not be shown.
+fn time_str(time: i64, format: &str) -> String {
+    match DateTime::from_timestamp(time, 0) {
+        Some(time) => time.with_timezone(&Local).format(format).to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// Local time with the timezone, example: 2026-10-18 22:00:00 +08:00.
+fn full_time_str(time: Option<i64>) -> String {
+    match time.and_then(|t| DateTime::from_timestamp(t, 0)) {
+        Some(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+            .to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// The full path of the program, found in `$PATH` like the executor does.
+fn program_path(program: &str, cwd: Option<&str>) -> String {
+    if program.contains('/') {
+        let path = match cwd {
+            Some(cwd) => Path::new(cwd).join(program),
+            None => PathBuf::from(program),
+        };
+        return path.display().to_string();
+    }
+    match get_exec_path(program) {
+        path if path.is_empty() => format!("{} (not found in $PATH)", program),
+        path => path,
+    }
+}
+
+/// The environment of the job compared with the current one,
+/// `+` added, `~` changed and `-` removed (only with `--env-all`).
+fn env_diff(saved: &[(String, String)], env_clear: bool) -> Vec<String> {
+    let current: BTreeMap<String, String> = env::vars().collect();
+    let mut diff = Vec::new();
+    for (key, value) in saved {
+        match current.get(key) {
+            None => diff.push(format!("+ {}={}", key, value)),
+            Some(now) if now != value => diff.push(format!("~ {}={} (now: {})", key, value, now)),
+            Some(_) => (),
+        }
+    }
+    if env_clear {
+        for key in current.keys() {
+            if !saved.iter().any(|(k, _)| k == key) {
+                diff.push(format!("- {}", key));
+            }
+        }
+    }
+    diff
+}
+
+/// Everything about one job, one field per line.
+fn show_detail(out: &mut impl Write, db: &SqliteDB, c: &Commands, noemoji: bool) -> Result<()> {
+    let env = db.select_env(c.id)?;
+    writeln!(out, "id:         {}", c.id)?;
+    match c
+        .kill_requested
+        .filter(|_| matches!(c.status, JobStatus::Running | JobStatus::Paused))
+    {
+        Some(kill) => writeln!(
+            out,
+            "status:     {} {}, stopping ({})",
+            status_symbol(c.status, noemoji).trim(),
+            c.status.name(),
+            kill.name()
+        )?,
+        None => writeln!(
+            out,
+            "status:     {} {}",
+            status_symbol(c.status, noemoji).trim(),
+            c.status.name()
+        )?,
+    }
+    writeln!(out, "command:    {}", c.command)?;
+    writeln!(out, "argv:       {}", serde_json::to_string(&c.argv)?)?;
+    writeln!(out, "mode:       {}", c.mode)?;
+    match Executor::new(c, Vec::new()).resolve() {
+        Ok(program) => {
+            let path = program_path(&program[0], c.cwd.as_deref());
+            let args = shlex::try_join(program[1..].iter().map(|a| a.as_str()))?;
+            writeln!(out, "run:        {} {}", path, args)?;
+        }
+        Err(e) => writeln!(out, "run:        {}", e)?,
+    }
+    writeln!(out, "cwd:        {}", c.cwd.as_deref().unwrap_or("-"))?;
+    writeln!(out, "user:       {}", c.user)?;
+    writeln!(out, "queue:      {}", c.queue)?;
+    writeln!(out, "priority:   {}", priority_name(c.priority))?;
+    let deps = db.select_dependencies(c.id)?;
+    if !deps.is_empty() {
+        let mut after = Vec::new();
+        for (dep, kind) in deps {
+            let status = match db.select_by_id(dep)? {
+                Some(d) => d.status.name(),
+                None => "deleted",
+            };
+            after.push(format!("{}:{}({})", kind, dep, status));
+        }
+        writeln!(out, "after:      {}", after.join(" "))?;
+    }
+    writeln!(out, "added:      {}", full_time_str(Some(c.add_time)))?;
+    if let Some(not_before) = c.not_before {
+        writeln!(out, "not before: {}", full_time_str(Some(not_before)))?;
+    }
+    writeln!(
+        out,
+        "started:    {}",
+        full_time_str(Some(c.start_time).filter(|t| *t != -1))
+    )?;
+    writeln!(
+        out,
+        "finished:   {}",
+        full_time_str(Some(c.finish_time).filter(|t| *t != -1))
+    )?;
+    if let Some(retry_at) = c.retry_at {
+        writeln!(out, "retry at:   {}", full_time_str(Some(retry_at)))?;
+    }
+    match (c.exit_code, c.signal) {
+        (Some(code), _) => writeln!(out, "exit:       exit({})", code)?,
+        (None, Some(signal)) => writeln!(out, "exit:       killed({})", signal_name(signal))?,
+        (None, None) => (),
+    }
+    if let Some(timeout) = c.timeout {
+        writeln!(out, "timeout:    {}", format_duration(timeout))?;
+    }
+    if c.retries > 0 {
+        writeln!(
+            out,
+            "retries:    {} | delay({}) | backoff({}x)",
+            c.retries,
+            format_duration(c.retry_delay),
+            c.retry_backoff
+        )?;
+    }
+    if let (Some(user_time), Some(system_time), Some(max_rss)) =
+        (c.user_time, c.system_time, c.max_rss)
+    {
+        writeln!(
+            out,
+            "usage:      user({:.2}s) | system({:.2}s) | max rss({:.1} MiB)",
+            user_time,
+            system_time,
+            max_rss as f64 / 1024.0
+        )?;
+    }
+    if c.env_clear {
+        writeln!(out, "env:        only the saved environment")?;
+    } else if env.is_empty() {
+        writeln!(out, "env:        the environment of the executor")?;
+    } else {
+        writeln!(
+            out,
+            "env:        the environment of the executor and the saved variables"
+        )?;
+    }
+    for line in env_diff(&env, c.env_clear) {
+        writeln!(out, "            {}", line)?;
+    }
+    writeln!(
+        out,
+        "stdout:     {}",
+        c.stdout_log.as_deref().unwrap_or("-")
+    )?;
+    writeln!(
+        out,
+        "stderr:     {}",
+        c.stderr_log.as_deref().unwrap_or("-")
+    )?;
+    Ok(())
+}
+
+/// Show the job and the history of its attempts.
+pub fn show(id: i32, noemoji: bool, format: Format) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let c = match db.select_by_id(id)? {
+        Some(c) => c,
+        None => {
+            println!("Job [{}] not exists!", id);
+            return Ok(());
+        }
+    };
+    ignore_broken_pipe(show_output(
+        &mut io::stdout().lock(),
+        &db,
+        &c,
+        noemoji,
+        format,
+    ))
+}
+
+/// The job in the format, the text one ends with the history of the attempts.
+fn show_output(
+    out: &mut impl Write,
+    db: &SqliteDB,
+    c: &Commands,
+    noemoji: bool,
+    format: Format,
+) -> Result<()> {
+    match format {
+        Format::Table => (),
+        // a single object instead of an array of one job
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, &job_view(db, c, true)?)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        format => return commands_output(out, &[job_view(db, c, true)?], format),
+    }
+    show_detail(out, db, c, noemoji)?;
+    let attempts = db.select_attempts(c.id)?;
+    writeln!(out, "attempts:")?;
+    if attempts.is_empty() {
+        writeln!(out, "No attempt yet!")?;
+    }
+    for a in attempts {
+        let start_time = DateTime::from_timestamp(a.start_time, 0)
+            .unwrap()
+            .with_timezone(&Local);
+        let finish_time = DateTime::from_timestamp(a.finish_time, 0)
+            .unwrap()
+            .with_timezone(&Local);
+        let exit_str = match (a.exit_code, a.signal) {
+            (Some(code), _) => format!(" | exit({})", code),
+            (None, Some(signal)) => format!(" | killed({})", signal_name(signal)),
+            (None, None) => String::new(),
+        };
+        writeln!(
+            out,
+            "#{} {} | start({}) | finish({}) | used({}){} | {}",
+            a.attempt,
+            status_symbol(a.status, noemoji),
+            start_time.format("%m-%d %H:%M:%S"),
+            finish_time.format("%m-%d %H:%M:%S"),
+            format_duration(a.finish_time - a.start_time),
+            exit_str,
+            a.stdout_log.unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn logs(id: i32, follow: bool, stderr: bool) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let c = match db.select_by_id(id)? {
+        Some(c) => c,
+        None => {
+            println!("Job [{}] not exists!", id);
+            return Ok(());
+        }
+    };
+    let log = if stderr { c.stderr_log } else { c.stdout_log };
+    let log = match log {
+        Some(log) => log,
+        None => {
+            println!("Job [{}] has no log yet!", id);
+            return Ok(());
+        }
+    };
+
+    let mut file = File::open(&log)?;
+    let mut stdout = io::stdout();
+    io::copy(&mut file, &mut stdout)?;
+    if follow {
+        // keep printing the new output until the job is reaped, a paused job or
+        // a job being killed is still running
+        let duration = time::Duration::from_secs_f32(0.5);
+        let mut buf = Vec::new();
+        loop {
+            let running = match db.select_by_id(id)? {
+                Some(c) => matches!(c.status, JobStatus::Running | JobStatus::Paused),
+                None => false,
+            };
+            buf.clear();
+            file.read_to_end(&mut buf)?;
+            stdout.write_all(&buf)?;
+            stdout.flush()?;
+            if !running {
+                break;
+            }
+            thread::sleep(duration);
+        }
+    }
+    Ok(())
+}
+
+/// What to do with the fires missed while no executor was running.
+static MISSED_POLICIES: [&str; 3] = ["skip", "once", "all"];
+/// Seconds after the fire time the fire is still on time, not missed.
+const CRON_LATE: i64 = 60;
+
+/// The first fire time after the UTC timestamp, the cron expression is in local time.
+fn next_fire(cron: &Cron, time: i64) -> Option<i64> {
+    let mut t = DateTime::from_timestamp(time, 0)?
+        .with_timezone(&Local)
+        .naive_local();
+    loop {
+        t = cron.next_after(t)?;
+        // the local time not exists when the clock jumps forward (DST)
+        if let Some(fire) = Local.from_local_datetime(&t).earliest() {
+            return Some(fire.timestamp());
+        }
+    }
+}
+
+pub fn cron_add(
+    expr: &str,
+    command: &str,
+    executor: Option<&str>,
+    shell: bool,
+    missed: &str,
+    queue: &str,
+) -> Result<()> {
+    let cron = match Cron::parse(expr) {
+        Some(cron) => cron,
+        None => {
+            println!("Wrong cron expression: {} (example: \"0 3 * * *\")", expr);
+            return Ok(());
+        }
+    };
+    if !MISSED_POLICIES.contains(&missed) {
+        println!("Wrong missed policy: {} (skip, once or all)", missed);
+        return Ok(());
+    }
+    if !valid_queue_name(queue) {
+        println!("Wrong queue name: {}", queue);
+        return Ok(());
+    }
+    let db = SqliteDB::new()?;
+    let add_time = Utc::now().timestamp();
+    let user = get_username();
+    let cm = match new_job(&db, command, executor, shell, &user, add_time)? {
+        Some(cm) => cm,
+        None => return Ok(()),
+    };
+    let next_run = next_fire(&cron, add_time);
+    let id = db.insert_schedule(&Schedules {
+        id: 0,
+        expr: expr.to_string(),
+        user: cm.user,
+        command: cm.command,
+        argv: cm.argv,
+        mode: cm.mode,
+        executor: cm.executor,
+        cwd: cm.cwd,
+        missed: missed.to_string(),
+        queue: queue.to_string(),
+        next_run,
+        add_time,
+    })?;
+    println!(
+        "Schedule [{}] added, next run {}",
+        id,
+        fire_time_str(next_run)
+    );
     Ok(())
 }
+
+fn fire_time_str(time: Option<i64>) -> String {
+    match time {
+        Some(time) => DateTime::from_timestamp(time, 0)
+            .unwrap()
+            .with_timezone(&Local)
+            .format("%m-%d %H:%M")
+            .to_string(),
+        None => String::from("never"),
+    }
+}
+
+pub fn cron_list() -> Result<()> {
+    let db = SqliteDB::new()?;
+    let schedules = db.select_schedules()?;
+    if schedules.is_empty() {
+        println!("No schedule!");
+    }
+    for s in schedules {
+        let command = if s.mode == "shell" {
+            format!("$ {}", s.command)
+        } else {
+            s.command
+        };
+        println!(
+            "{} | {} | {} | queue({}) | missed({}) | next({})",
+            s.id,
+            s.expr,
+            command,
+            s.queue,
+            s.missed,
+            fire_time_str(s.next_run)
+        );
+    }
+    Ok(())
+}
+
+pub fn cron_remove(id: i32) -> Result<()> {
+    let db = SqliteDB::new()?;
+    if db.remove_schedule(id)? {
+        println!("Schedule [{}] removed", id);
+    } else {
+        println!("Schedule [{}] not exists!", id);
+    }
+    Ok(())
+}
+
+/// Add the jobs of the schedules which fired since the last round,
+/// the fires missed while no executor was running follow the missed policy.
+fn fire_schedules(db: &SqliteDB, queues: &[String], now: i64) -> Result<()> {
+    for s in db.select_schedules()? {
+        // the executor of the queue adds the jobs
+        if !queues.contains(&s.queue) {
+            continue;
+        }
+        let next_run = match s.next_run {
+            Some(next_run) if next_run <= now => next_run,
+            _ => continue,
+        };
+        let cron = match Cron::parse(&s.expr) {
+            Some(cron) => cron,
+            None => continue,
+        };
+        let mut fires = vec![next_run];
+        while let Some(fire) = next_fire(&cron, *fires.last().unwrap()) {
+            if fire > now {
+                break;
+            }
+            fires.push(fire);
+        }
+        let last_fire = *fires.last().unwrap();
+        let runs = match s.missed.as_str() {
+            "all" => fires.len(),
+            "once" => 1,
+            _ if now - last_fire < CRON_LATE => 1,
+            _ => 0,
+        };
+        if runs < fires.len() {
+            println!(
+                ">>> Cron [{}]: {} missed fire(s) skipped",
+                s.id,
+                fires.len() - runs
+            );
+        }
+        for _ in 0..runs {
+            let mut cm = Commands::new(&s.user, s.argv.clone(), &s.executor, now);
+            cm.command = s.command.clone();
+            cm.mode = s.mode.clone();
+            cm.cwd = s.cwd.clone();
+            cm.queue = s.queue.clone();
+            cm.position = db.next_position()?;
+            let id = db.insert(&cm)?;
+            println!(">>> Cron [{}]: job [{}] added", s.id, id);
+        }
+        db.update_next_run(s.id, next_fire(&cron, now))?;
+    }
+    Ok(())
+}
+
+/// Settings which can be changed by `lucq config <key> <value>`.
+static CONFIG_KEYS: [(&str, &str); 3] = [
+    ("shell", "shell used by the --shell jobs"),
+    (
+        "timeout",
+        "timeout of the jobs added without --timeout, example: 12h",
+    ),
+    (
+        "timeout_signals",
+        "signals send to the timed out jobs, default: TERM:10s,KILL",
+    ),
+];
+
+pub fn config(key: Option<&str>, value: Option<&str>, unset: bool) -> Result<()> {
+    let db = SqliteDB::new()?;
+    let key = match key {
+        Some(key) => key,
+        None => {
+            for (k, desc) in CONFIG_KEYS {
+                let v = db.get_setting(k)?.unwrap_or(String::from("(default)"));
+                println!("{} = {}    # {}", k, v, desc);
+            }
+            return Ok(());
+        }
+    };
+    if !CONFIG_KEYS.iter().any(|(k, _)| *k == key) {
+        println!("Unknown config key: {}", key);
+        return Ok(());
+    }
+    if unset {
+        db.remove_setting(key)?;
+    } else if let Some(value) = value {
+        let valid = match key {
+            "timeout" => parse_duration(value).is_some(),
+            "timeout_signals" => parse_signals(value).is_some(),
+            _ => true,
+        };
+        if !valid {
+            println!("Wrong value: {}", value);
+            return Ok(());
+        }
+        db.set_setting(key, value)?;
+    } else {
+        let v = db.get_setting(key)?.unwrap_or(String::from("(default)"));
+        println!("{} = {}", key, v);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2h30m"), Some(9000));
+        assert_eq!(parse_duration("1d 1s"), Some(86401));
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration("45"), Some(45));
+        assert_eq!(parse_duration("3x"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration(""), None);
+        // overflow
+        assert_eq!(parse_duration("99999999999999999d"), None);
+        assert_eq!(parse_duration("106751991167300d 106751991167300d"), None);
+    }
+    #[test]
+    fn test_parse_signals() {
+        assert_eq!(parse_signals("TERM:10s,KILL"), Some(vec![(15, 10), (9, 0)]));
+        assert_eq!(parse_signals("SIGINT:1m, 9"), Some(vec![(2, 60), (9, 0)]));
+        assert_eq!(parse_signals("TERM:x"), None);
+        assert_eq!(parse_signals("NOPE"), None);
+    }
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(parse_backoff("2x"), Some(2.0));
+        assert_eq!(parse_backoff("1.5"), Some(1.5));
+        assert_eq!(parse_backoff("0.5x"), None);
+        assert_eq!(parse_backoff("x"), None);
+        assert_eq!(retry_delay(30, 2.0, 1), 30);
+        assert_eq!(retry_delay(30, 2.0, 3), 120);
+        assert_eq!(retry_delay(10, 1.5, 2), 15);
+    }
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(parse_priority("high"), Some(10));
+        assert_eq!(parse_priority("low"), Some(-10));
+        assert_eq!(parse_priority("-3"), Some(-3));
+        assert_eq!(parse_priority("urgent"), None);
+        assert_eq!(priority_name(parse_priority("normal").unwrap()), "normal");
+        assert_eq!(priority_name(5), "5");
+    }
+    #[test]
+    fn test_parse_start_time() {
+        let now = Local.with_ymd_and_hms(2026, 10, 18, 21, 0, 0).unwrap();
+        let at = |y, mo, d, h, mi| {
+            Local
+                .with_ymd_and_hms(y, mo, d, h, mi, 0)
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(
+            parse_start_time("2026-10-19 22:00", now),
+            Some(at(2026, 10, 19, 22, 0))
+        );
+        assert_eq!(
+            parse_start_time("22:00", now),
+            Some(at(2026, 10, 18, 22, 0))
+        );
+        assert_eq!(
+            parse_start_time("20:30", now),
+            Some(at(2026, 10, 19, 20, 30))
+        );
+        assert_eq!(parse_start_time("tonight", now), None);
+    }
+    #[test]
+    fn test_command_to_job() {
+        let c = command_to_job("python3 -c 'print(1)'", "null", "u", 0).unwrap();
+        assert_eq!(c.argv, vec!["python3", "-c", "print(1)"]);
+        let c = command_to_job("bash -c 'echo a.sh'", "null", "u", 0).unwrap();
+        assert_eq!(c.argv, vec!["bash", "-c", "echo a.sh"]);
+        // a typed interpreter is never dropped
+        let c = command_to_job("python3 src/main.rs -a 1", "null", "u", 0).unwrap();
+        assert_eq!(c.argv, vec!["python3", "src/main.rs", "-a", "1"]);
+        // the file in the arguments does not choose the interpreter
+        let c = command_to_job("cat src/main.rs", "null", "u", 0).unwrap();
+        let executor = Executor::new(&c, Vec::new());
+        assert_eq!(executor.resolve().unwrap(), vec!["cat", "src/main.rs"]);
+        // the script run directly gets its full path
+        let c = command_to_job("src/main.rs -a 1", "null", "u", 0).unwrap();
+        assert!(c.argv[0].ends_with("/src/main.rs"));
+        assert_eq!(c.argv[1..], ["-a", "1"]);
+    }
+    #[test]
+    fn test_env_diff() {
+        let path = env::var("PATH").unwrap_or_default();
+        let saved = vec![
+            (String::from("LUCQ_TEST_NOT_SET"), String::from("1")),
+            (String::from("PATH"), format!("{}:/opt/bin", path)),
+        ];
+        assert_eq!(
+            env_diff(&saved, false),
+            vec![
+                String::from("+ LUCQ_TEST_NOT_SET=1"),
+                format!("~ PATH={}:/opt/bin (now: {})", path, path),
+            ]
+        );
+        assert!(env_diff(&saved, true).contains(&String::from("- HOME")));
+    }
+    #[test]
+    fn test_format() {
+        assert_eq!("csv".parse::<Format>(), Ok(Format::Csv));
+        assert!("xml".parse::<Format>().is_err());
+    }
+    #[test]
+    fn test_ids() {
+        assert_eq!("3".parse::<Ids>().unwrap().0, vec![3]);
+        assert_eq!("1-4".parse::<Ids>().unwrap().0, vec![1, 2, 3, 4]);
+        assert_eq!("1,3, 5-6".parse::<Ids>().unwrap().0, vec![1, 3, 5, 6]);
+        assert!("5-1".parse::<Ids>().is_err());
+        assert!("a".parse::<Ids>().is_err());
+        assert!("1-2-3".parse::<Ids>().is_err());
+    }
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("CUDA_*", "CUDA_VISIBLE_DEVICES"));
+        assert!(wildcard_match("*PATH", "LD_LIBRARY_PATH"));
+        assert!(wildcard_match("VIRTUAL_ENV", "VIRTUAL_ENV"));
+        assert!(wildcard_match("CONDA_?REFIX", "CONDA_PREFIX"));
+        assert!(wildcard_match("*_*_*", "A_B_C"));
+        assert!(!wildcard_match("CUDA_*", "PATH"));
+        assert!(!wildcard_match("PATH", "PATHS"));
+    }
+}
@@ -0,0 +1,132 @@
+//! The flags of the old command line (`lucq --add`, `lucq -m exec`...),
+//! kept so the existing scripts work while they move to the subcommands.
+use clap::{ArgAction, Parser};
+
+use crate::func::{Format, Ids, DEFAULT_QUEUE};
+use crate::{AddArgs, Cli, Command};
+
+/// The flags which choose the action in the old command line.
+static ACTION_FLAGS: [&str; 14] = [
+    "-m", "--mode", "-a", "--add", "-d", "--delete", "--cancel", "-g", "--grep", "-l", "--list",
+    "-c", "--clean", "--align",
+];
+
+#[derive(Parser, Debug)]
+#[command(name = "lucq")]
+struct LegacyArgs {
+    /// Run mode (cli or exec)
+    #[arg(short, long, value_name = "mode", default_value = "cli")]
+    mode: String,
+
+    /// Add one command
+    #[arg(short, long, value_name = "job")]
+    add: Option<String>,
+
+    #[command(flatten)]
+    opts: AddArgs,
+
+    /// Delete command(s) (example: 1 or 1-5)
+    #[arg(short, long, value_name = "id(s)")]
+    delete: Option<Ids>,
+
+    /// Cancel command(s) (keep it in history but not run, example: 1 or 1-5)
+    #[arg(long, value_name = "id(s)")]
+    cancel: Option<Ids>,
+
+    /// Search and show
+    #[arg(short, long, value_name = "name")]
+    grep: Option<String>,
+
+    /// List all commands
+    #[arg(short, long, action(ArgAction::SetTrue))]
+    list: bool,
+
+    /// Clean database
+    #[arg(short, long, action(ArgAction::SetTrue))]
+    clean: bool,
+
+    /// Renumber the positions of the jobs
+    #[arg(long, action(ArgAction::SetTrue))]
+    align: bool,
+
+    /// Number of jobs run at the same time (exec mode)
+    #[arg(short, long, value_name = "n", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    slots: u32,
+
+    /// Do not use emoji
+    #[arg(long, action(ArgAction::SetTrue))]
+    noemoji: bool,
+}
+
+/// The old command line starts with a flag and uses one of the action flags,
+/// `lucq --noemoji list` is not old.
+pub fn is_legacy(argv: &[String]) -> bool {
+    match argv.get(1) {
+        Some(first) if first.starts_with('-') => argv.iter().skip(1).any(|arg| {
+            let flag = arg.split('=').next().unwrap_or(arg);
+            ACTION_FLAGS.contains(&flag)
+        }),
+        _ => false,
+    }
+}
+
+fn deprecated(old: &str, new: &str) {
+    eprintln!(">>> `lucq {}` is deprecated, use `lucq {}`", old, new);
+}
+
+/// Parse the old command line into the subcommand it means,
+/// `None` if it does nothing (example: `lucq -m cli`).
+pub fn parse(argv: &[String]) -> Option<Cli> {
+    let args = LegacyArgs::parse_from(argv);
+    let command = if args.clean {
+        deprecated("--clean", "clean");
+        Command::Clean
+    } else if args.mode == "exec" {
+        deprecated("--mode exec", "exec");
+        let queue = match args.opts.queue {
+            Some(queues) => queues.split(',').map(|q| q.trim().to_string()).collect(),
+            None => vec![DEFAULT_QUEUE.to_string()],
+        };
+        Command::Exec {
+            slots: args.slots,
+            queue,
+        }
+    } else if args.mode != "cli" {
+        return None;
+    } else if let Some(command) = args.add {
+        deprecated("--add", "add");
+        Command::Add {
+            command,
+            opts: Box::new(args.opts),
+        }
+    } else if let Some(ids) = args.delete {
+        deprecated("--delete", "rm");
+        Command::Rm { ids }
+    } else if let Some(ids) = args.cancel {
+        deprecated("--cancel", "cancel");
+        Command::Cancel { ids }
+    } else if let Some(name) = args.grep {
+        deprecated("--grep", "grep");
+        Command::Grep {
+            name,
+            format: Format::Table,
+        }
+    } else if args.list {
+        deprecated("--list", "list");
+        Command::List {
+            queue: args.opts.queue,
+            format: Format::Table,
+            watch: None,
+            until_empty: false,
+        }
+    } else if args.align {
+        deprecated("--align", "align");
+        Command::Align
+    } else {
+        return None;
+    };
+    Some(Cli {
+        command,
+        noemoji: args.noemoji,
+    })
+}
@@ -1,69 +1,360 @@
 use anyhow::Result;
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand};
 use once_cell::sync::Lazy;
+use std::env;
 use std::process;
 use std::sync::Mutex;
 use std::{thread, time};
 
+pub mod cron;
 pub mod executor;
 pub mod func;
+mod legacy;
 pub mod sqlitedb;
-use func::{add, align, cancel, clean, delete, exec, grep, list};
+mod tui;
+use executor::signal_group;
+use func::{
+    add, align, cancel, clean, config, cron_add, cron_list, cron_remove, delete, exec, grep, kill,
+    list, list_watch, logs, move_job, pause, queue, queue_pause, queue_set_slots, resume, retry,
+    show, status, AddOptions, Format, Ids, DEFAULT_QUEUE,
+};
+use sqlitedb::MoveTo;
 
 static SQLITE_DB: &str = "lucq.sql";
 static USER_QUIT_OP: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+/// Process groups of the jobs started by this executor.
+static RUNNING_GROUPS: Lazy<Mutex<Vec<i32>>> = Lazy::new(|| Mutex::new(Vec::new()));
 
 /// Linux user command queue
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
-    /// Run mode (cli or exec)
-    #[arg(short, long, value_name = "mode", default_value = "cli")]
-    mode: String,
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 
-    /// Add one command
-    #[arg(short, long, value_name = "job", default_value = "null")]
-    add: String,
+    /// Do not use emoji
+    #[arg(long, global = true, action(ArgAction::SetTrue))]
+    noemoji: bool,
+}
+
+/// Options of `lucq add`, also accepted by the legacy `lucq --add`.
+#[derive(Args, Debug)]
+struct AddArgs {
+    /// Run the command with the shell (`$SHELL -c`), allow pipes, redirections and `&&`
+    #[arg(long, action(ArgAction::SetTrue))]
+    shell: bool,
 
-    /// Add one command before <id>
-    #[arg(long, value_name = "id", default_value_t = -1)]
-    before: i32,
+    /// Executor path (example: /usr/bin/python3)
+    #[arg(short, long, value_name = "path")]
+    executor: Option<String>,
 
-    /// Add one command after <id>
-    #[arg(long, value_name = "id", default_value_t = -1)]
-    after: i32,
+    /// Save the whole environment with the command and run it with only this environment
+    #[arg(long, action(ArgAction::SetTrue))]
+    env_all: bool,
 
-    /// Delete command(s) (example: 1 or 1-5)
-    #[arg(short, long, value_name = "id(s)", default_value = "null")]
-    delete: String,
+    /// Save the environment variables match the pattern with the command (example: CUDA_*)
+    #[arg(long, value_name = "pattern")]
+    env_keep: Vec<String>,
 
-    /// Cancel command(s) (keep it in history but not run, example: 1 or 1-5)
-    #[arg(long, value_name = "id(s)", default_value = "null")]
-    cancel: String,
+    /// Set an environment variable for the command (example: K=V)
+    #[arg(long, value_name = "K=V")]
+    env: Vec<String>,
 
-    /// Executor path (example: /usr/bin/python3)
-    #[arg(short, long, value_name = "path", default_value = "null")]
-    executor: String,
+    /// Put the command back into the queue if the executor crashed while running it
+    #[arg(long, action(ArgAction::SetTrue))]
+    requeue_on_crash: bool,
+
+    /// Stop the command if it runs longer than the duration (example: 2h30m)
+    #[arg(long, value_name = "duration")]
+    timeout: Option<String>,
+
+    /// Run the command again up to n times if it failed
+    #[arg(long, value_name = "n", default_value_t = 0)]
+    retries: u32,
+
+    /// Wait before the first retry (example: 30s)
+    #[arg(long, value_name = "duration")]
+    retry_delay: Option<String>,
+
+    /// Multiply the retry delay after every retry (example: 2x)
+    #[arg(long, value_name = "factor")]
+    retry_backoff: Option<String>,
+
+    /// Run the command after the job(s) finished successfully (example: 12,13)
+    #[arg(long, value_name = "id(s)", value_delimiter = ',')]
+    after_ok: Vec<i32>,
+
+    /// Run the command after the job(s) ended, whatever the result (example: 14)
+    #[arg(long, value_name = "id(s)", value_delimiter = ',')]
+    after_any: Vec<i32>,
+
+    /// Run the command only if the job(s) failed (example: 15)
+    #[arg(long, value_name = "id(s)", value_delimiter = ',')]
+    after_fail: Vec<i32>,
+
+    /// Priority of the command, the higher runs first (high, normal, low or a number)
+    #[arg(long, value_name = "priority")]
+    priority: Option<String>,
+
+    /// Do not start the command before the local time (example: "2026-10-19 22:00" or 22:00)
+    #[arg(long, value_name = "time", conflicts_with = "in")]
+    at: Option<String>,
+
+    /// Do not start the command before the duration passed (example: 3h)
+    #[arg(long, value_name = "duration")]
+    r#in: Option<String>,
+
+    /// Put the command before <id>
+    #[arg(long, value_name = "id", conflicts_with = "after")]
+    before: Option<i32>,
+
+    /// Put the command after <id>
+    #[arg(long, value_name = "id")]
+    after: Option<i32>,
+
+    /// Queue of the command [default: default]
+    #[arg(short, long, value_name = "name")]
+    queue: Option<String>,
+}
+
+impl AddArgs {
+    fn into_options(self) -> AddOptions {
+        AddOptions {
+            executor: self.executor,
+            shell: self.shell,
+            before: self.before,
+            after: self.after,
+            env_all: self.env_all,
+            env_keep: self.env_keep,
+            env: self.env,
+            requeue_on_crash: self.requeue_on_crash,
+            timeout: self.timeout,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            retry_backoff: self.retry_backoff,
+            after_ok: self.after_ok,
+            after_any: self.after_any,
+            after_fail: self.after_fail,
+            priority: self.priority,
+            at: self.at,
+            r#in: self.r#in,
+            queue: self.queue.unwrap_or(DEFAULT_QUEUE.to_string()),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum QueueAction {
+    /// Stop starting new jobs, the running jobs keep running
+    Pause {
+        /// Queue name, all the queues if empty
+        name: Option<String>,
+    },
+    /// Start new jobs again
+    Resume {
+        /// Queue name, all the queues if empty
+        name: Option<String>,
+    },
+    /// Limit the number of the running jobs in the queue, 0 means no limit
+    Slots {
+        /// Queue name
+        name: String,
+
+        /// Max number of the running jobs
+        n: u32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CronAction {
+    /// Add a recurring command (example: lucq cron add "0 3 * * *" ./sync.sh)
+    Add {
+        /// Cron expression: minute hour day-of-month month day-of-week, or @daily, @weekly...
+        expr: String,
+
+        /// The command
+        command: String,
+
+        /// Run the command with the shell (`$SHELL -c`)
+        #[arg(long, action(ArgAction::SetTrue))]
+        shell: bool,
+
+        /// Executor path (example: /usr/bin/python3)
+        #[arg(short, long, value_name = "path")]
+        executor: Option<String>,
+
+        /// What to do with the fires missed while no executor was running (skip, once or all)
+        #[arg(long, value_name = "policy", default_value = "skip")]
+        missed: String,
+
+        /// Queue of the added jobs
+        #[arg(long, value_name = "name", default_value = DEFAULT_QUEUE)]
+        queue: String,
+    },
+    /// Show the recurring commands
+    List,
+    /// Remove a recurring command, the added jobs are kept
+    Remove {
+        /// Schedule id
+        id: i32,
+    },
+}
 
-    /// Search and show
-    #[arg(short, long, value_name = "name", default_value = "null")]
-    grep: String,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add one command into the queue
+    Add {
+        /// The command (example: "test.py -a 1")
+        command: String,
 
+        #[command(flatten)]
+        opts: Box<AddArgs>,
+    },
+    /// Run the commands in the queue
+    Exec {
+        /// Number of jobs run at the same time
+        #[arg(short, long, value_name = "n", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        slots: u32,
+
+        /// The served queues in priority order (example: fast,default)
+        #[arg(short, long, value_name = "name(s)", value_delimiter = ',', default_value = DEFAULT_QUEUE)]
+        queue: Vec<String>,
+    },
     /// List all commands
-    #[arg(short, long, action(ArgAction::SetTrue))]
-    list: bool,
+    List {
+        /// Only list the commands in the queue
+        #[arg(short, long, value_name = "name")]
+        queue: Option<String>,
 
-    /// Clean database
-    #[arg(short, long, action(ArgAction::SetTrue))]
-    clean: bool,
+        /// Output format (table, json, jsonl or csv)
+        #[arg(long, value_name = "format", default_value = "table")]
+        format: Format,
 
-    /// Align database
-    #[arg(long, action(ArgAction::SetTrue))]
-    align: bool,
+        /// Redraw the list every <seconds>, the changed commands are highlighted
+        #[arg(long, value_name = "seconds", num_args = 0..=1, default_missing_value = "2", value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "format")]
+        watch: Option<u64>,
 
-    /// Do not use emoji
-    #[arg(long, action(ArgAction::SetTrue))]
-    noemoji: bool,
+        /// Stop watching when no command is waiting or running
+        #[arg(long, action(ArgAction::SetTrue), requires = "watch")]
+        until_empty: bool,
+    },
+    /// Search the commands and show
+    Grep {
+        /// Part of the command
+        name: String,
+
+        /// Output format (table, json, jsonl or csv)
+        #[arg(long, value_name = "format", default_value = "table")]
+        format: Format,
+    },
+    /// Delete command(s) and their logs
+    Rm {
+        /// Job id(s) (example: 1, 1-5 or 1,3,5-7)
+        ids: Ids,
+    },
+    /// Cancel waiting command(s), keep them in history but not run
+    Cancel {
+        /// Job id(s) (example: 1, 1-5 or 1,3,5-7)
+        ids: Ids,
+    },
+    /// Put a finished command back into the queue
+    Retry {
+        /// Job id
+        id: i32,
+    },
+    /// Print the output of a job
+    Logs {
+        /// Job id
+        id: i32,
+
+        /// Keep printing new output while the job is running or paused
+        #[arg(short, long, action(ArgAction::SetTrue))]
+        follow: bool,
+
+        /// Print stderr instead of stdout
+        #[arg(long, action(ArgAction::SetTrue))]
+        stderr: bool,
+    },
+    /// Show a job and the history of its attempts
+    Show {
+        /// Job id
+        id: i32,
+
+        /// Output format (table, json, jsonl or csv)
+        #[arg(long, value_name = "format", default_value = "table")]
+        format: Format,
+    },
+    /// Move a job in the queue, the id of the job does not change
+    #[command(group(ArgGroup::new("to").required(true).args(["before", "after", "top", "bottom"])))]
+    Move {
+        /// Job id
+        id: i32,
+
+        /// Move the job before <id>
+        #[arg(long, value_name = "id")]
+        before: Option<i32>,
+
+        /// Move the job after <id>
+        #[arg(long, value_name = "id")]
+        after: Option<i32>,
+
+        /// Move the job to the head of the queue
+        #[arg(long, action(ArgAction::SetTrue))]
+        top: bool,
+
+        /// Move the job to the end of the queue
+        #[arg(long, action(ArgAction::SetTrue))]
+        bottom: bool,
+    },
+    /// Show the running executors
+    Status,
+    /// Stop a running job (SIGTERM, then SIGKILL after the grace period)
+    Kill {
+        /// Job id
+        id: i32,
+
+        /// Seconds to wait before SIGKILL
+        #[arg(long, value_name = "seconds", default_value_t = 10)]
+        grace: u64,
+    },
+    /// Pause a running job (SIGSTOP)
+    Pause {
+        /// Job id
+        id: i32,
+    },
+    /// Resume a paused job (SIGCONT)
+    Resume {
+        /// Job id
+        id: i32,
+    },
+    /// Pause or resume the queues, show the queue states if empty
+    Queue {
+        #[command(subcommand)]
+        action: Option<QueueAction>,
+    },
+    /// Add, show or remove the recurring commands
+    Cron {
+        #[command(subcommand)]
+        action: CronAction,
+    },
+    /// Show or change the settings (example: lucq config shell /bin/zsh)
+    Config {
+        /// Setting name, show all settings if empty
+        key: Option<String>,
+
+        /// New value
+        value: Option<String>,
+
+        /// Restore the default value
+        #[arg(long, action(ArgAction::SetTrue))]
+        unset: bool,
+    },
+    /// Watch and manage the queue in the terminal
+    Tui,
+    /// Renumber the positions of the jobs (the ids are not changed)
+    Align,
+    /// Delete the database and all the logs
+    Clean,
 }
 
 fn user_quit() -> bool {
@@ -74,10 +365,7 @@ fn user_quit() -> bool {
     let _ = std::io::stdin().read_line(&mut user_input).unwrap();
     let ui = user_input.trim().to_string();
 
-    match ui.as_str() {
-        "Y" | "y" | "Yes" | "YES" | "yes" | "Q" | "q" => true,
-        _ => false,
-    }
+    matches!(ui.as_str(), "Y" | "y" | "Yes" | "YES" | "yes" | "Q" | "q")
 }
 
 fn main() -> Result<()> {
@@ -85,7 +373,14 @@ fn main() -> Result<()> {
     ctrlc::set_handler(move || {
         *USER_QUIT_OP.lock().unwrap() = true;
         match user_quit() {
-            true => process::exit(0),
+            true => {
+                // the jobs are not in the terminal process group,
+                // stop them like the ctrl-c did
+                for pgid in RUNNING_GROUPS.lock().unwrap().iter() {
+                    signal_group(*pgid, libc::SIGINT);
+                }
+                process::exit(0)
+            }
             _ => {
                 println!(">>> Continue running...");
                 *USER_QUIT_OP.lock().unwrap() = false;
@@ -94,26 +389,81 @@ fn main() -> Result<()> {
     })
     .expect("error setting Ctrl-C handler");
 
-    let args = Args::parse();
-    if args.clean {
-        clean()?;
-    } else if args.mode == "cli" {
-        if args.add != "null" {
-            add(&args.add, &args.executor, args.before, args.after)?;
-        } else if args.delete != "null" {
-            delete(&args.delete)?;
-        } else if args.cancel != "null" {
-            cancel(&args.cancel)?;
-        } else if args.grep != "null" {
-            grep(&args.grep, args.noemoji)?;
-        } else if args.list {
-            list(args.noemoji)?;
-        } else if args.align {
-            align()?;
+    let argv: Vec<String> = env::args().collect();
+    let cli = if legacy::is_legacy(&argv) {
+        match legacy::parse(&argv) {
+            Some(cli) => cli,
+            None => {
+                Cli::command().print_help()?;
+                return Ok(());
+            }
+        }
+    } else {
+        Cli::parse_from(argv)
+    };
+    let noemoji = cli.noemoji;
+    match cli.command {
+        Command::Add { command, opts } => add(&command, &opts.into_options())?,
+        Command::Exec { slots, queue } => {
+            println!(">>> Running...");
+            exec(slots as usize, &queue)?
+        }
+        Command::List {
+            queue,
+            format,
+            watch,
+            until_empty,
+        } => match watch {
+            Some(interval) => list_watch(noemoji, queue.as_deref(), interval, until_empty)?,
+            None => list(noemoji, queue.as_deref(), format)?,
+        },
+        Command::Grep { name, format } => grep(&name, noemoji, format)?,
+        Command::Rm { ids } => delete(&ids.0)?,
+        Command::Cancel { ids } => cancel(&ids.0)?,
+        Command::Retry { id } => retry(id)?,
+        Command::Logs { id, follow, stderr } => logs(id, follow, stderr)?,
+        Command::Show { id, format } => show(id, noemoji, format)?,
+        Command::Move {
+            id,
+            before,
+            after,
+            top,
+            bottom: _,
+        } => {
+            let to = match (before, after, top) {
+                (Some(before), _, _) => MoveTo::Before(before),
+                (_, Some(after), _) => MoveTo::After(after),
+                (_, _, true) => MoveTo::Top,
+                _ => MoveTo::Bottom,
+            };
+            move_job(id, to)?
         }
-    } else if args.mode == "exec" {
-        println!(">>> Running...");
-        exec()?;
+        Command::Status => status()?,
+        Command::Kill { id, grace } => kill(id, grace)?,
+        Command::Pause { id } => pause(id)?,
+        Command::Resume { id } => resume(id)?,
+        Command::Queue { action } => match action {
+            Some(QueueAction::Pause { name }) => queue_pause(name.as_deref(), true)?,
+            Some(QueueAction::Resume { name }) => queue_pause(name.as_deref(), false)?,
+            Some(QueueAction::Slots { name, n }) => queue_set_slots(&name, n)?,
+            None => queue()?,
+        },
+        Command::Cron { action } => match action {
+            CronAction::Add {
+                expr,
+                command,
+                shell,
+                executor,
+                missed,
+                queue,
+            } => cron_add(&expr, &command, executor.as_deref(), shell, &missed, &queue)?,
+            CronAction::List => cron_list()?,
+            CronAction::Remove { id } => cron_remove(id)?,
+        },
+        Command::Config { key, value, unset } => config(key.as_deref(), value.as_deref(), unset)?,
+        Command::Tui => tui::tui(noemoji)?,
+        Command::Align => align()?,
+        Command::Clean => clean()?,
     }
     Ok(())
 }
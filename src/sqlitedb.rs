@@ -1,19 +1,471 @@
+use chrono::{DateTime, SecondsFormat};
 use home::home_dir;
-use rusqlite::{Connection, Result};
-use std::iter::zip;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+use rusqlite::Error::FromSqlConversionFailure;
+use rusqlite::{
+    params, Connection, OptionalExtension, Result, Row, ToSql, Transaction, TransactionBehavior,
+};
+use serde::{Serialize, Serializer};
+use std::time::Duration;
 
 use crate::SQLITE_DB;
 
-#[derive(Debug)]
+static COLUMNS: &str =
+    "id, user, command, executor, add_time, status, start_time, finish_time, stdout_log, stderr_log, exit_code, signal, argv, mode, cwd, env_clear, owner_pid, owner_host, owner_boot_id, requeue_on_crash, pid, pgid, timeout, retries, retry_delay, retry_backoff, attempt, retry_at, priority, position, not_before, queue, user_time, system_time, max_rss, kill_requested";
+
+/// Status of a job, stored as integer in the `status` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Waiting = 0,
+    Finished = 1,
+    Error = 2,
+    Cancelled = 3,
+    Paused = 4,
+    Killed = 5,
+    TimedOut = 6,
+    Skipped = 7,
+    Interrupted = 8,
+    Running = 9,
+}
+
+impl JobStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Waiting => "waiting",
+            JobStatus::Finished => "finished",
+            JobStatus::Error => "error",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Paused => "paused",
+            JobStatus::Killed => "killed",
+            JobStatus::TimedOut => "timed_out",
+            JobStatus::Skipped => "skipped",
+            JobStatus::Interrupted => "interrupted",
+            JobStatus::Running => "running",
+        }
+    }
+    pub fn from_i64(status: i64) -> Option<JobStatus> {
+        let status = match status {
+            0 => JobStatus::Waiting,
+            1 => JobStatus::Finished,
+            2 => JobStatus::Error,
+            3 => JobStatus::Cancelled,
+            4 => JobStatus::Paused,
+            5 => JobStatus::Killed,
+            6 => JobStatus::TimedOut,
+            7 => JobStatus::Skipped,
+            8 => JobStatus::Interrupted,
+            9 => JobStatus::Running,
+            _ => return None,
+        };
+        Some(status)
+    }
+}
+
+impl ToSql for JobStatus {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as i64))
+    }
+}
+
+impl FromSql for JobStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let status = value.as_i64()?;
+        JobStatus::from_i64(status).ok_or(FromSqlError::OutOfRange(status))
+    }
+}
+
+/// UTC timestamp as RFC 3339, -1 (not happened yet) as null.
+fn rfc3339<S: Serializer>(time: &i64, s: S) -> std::result::Result<S::Ok, S::Error> {
+    rfc3339_opt(&Some(*time).filter(|t| *t != -1), s)
+}
+
+fn rfc3339_opt<S: Serializer>(time: &Option<i64>, s: S) -> std::result::Result<S::Ok, S::Error> {
+    match time.and_then(|t| DateTime::from_timestamp(t, 0)) {
+        Some(time) => s.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        None => s.serialize_none(),
+    }
+}
+
+/// The "null" executor (run the command directly) as null.
+fn executor_opt<S: Serializer>(executor: &str, s: S) -> std::result::Result<S::Ok, S::Error> {
+    match executor {
+        "null" => s.serialize_none(),
+        executor => s.serialize_str(executor),
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Commands {
     pub id: i32,
     pub user: String,
     pub command: String,
+    pub argv: Vec<String>, // stored as json array
+    pub mode: String,      // argv or shell
+    #[serde(serialize_with = "executor_opt")]
     pub executor: String,
+    pub cwd: Option<String>, // working directory when the job was added
+    pub env_clear: bool,     // run with the saved environment only
+    #[serde(serialize_with = "rfc3339")]
     pub add_time: i64, // UTC timestamp
-    pub status: i32,   // 1 finish, 0 not finish, 2 error, 3 cancel, 9 running
+    pub status: JobStatus,
+    #[serde(serialize_with = "rfc3339")]
+    pub start_time: i64,
+    #[serde(serialize_with = "rfc3339")]
+    pub finish_time: i64,
+    pub stdout_log: Option<String>,
+    pub stderr_log: Option<String>,
+    pub exit_code: Option<i32>, // exit code of the process
+    pub signal: Option<i32>,    // signal which terminated the process
+    pub owner_pid: Option<i32>, // pid of the executor which runs the job
+    pub owner_host: Option<String>,
+    pub owner_boot_id: Option<String>,
+    pub requeue_on_crash: bool, // run the job again if the executor crashed
+    pub pid: Option<i32>,       // process of the job
+    pub pgid: Option<i32>,      // process group of the job and its children
+    pub timeout: Option<i64>,   // seconds
+    pub retries: i32,           // max number of the retries after the first run failed
+    pub retry_delay: i64,       // seconds before the first retry
+    pub retry_backoff: f64,     // the delay is multiplied by it after every retry
+    pub attempt: i32,           // number of the runs started
+    #[serde(serialize_with = "rfc3339_opt")]
+    pub retry_at: Option<i64>, // UTC timestamp of the next retry
+    pub priority: i32,          // the job with higher priority runs first
+    pub position: f64,          // order of the jobs with the same priority
+    #[serde(serialize_with = "rfc3339_opt")]
+    pub not_before: Option<i64>, // UTC timestamp, the job does not start before it
+    pub queue: String,
+    pub user_time: Option<f64>, // cpu seconds in user mode of the last run
+    pub system_time: Option<f64>, // cpu seconds in kernel mode of the last run
+    pub max_rss: Option<i64>,   // peak memory of the last run in KiB
+    // Killed or TimedOut, the job keeps running until the executor reaps it
+    pub kill_requested: Option<JobStatus>,
+}
+
+impl Commands {
+    pub fn new(user: &str, argv: Vec<String>, executor: &str, add_time: i64) -> Commands {
+        Commands {
+            id: 0,
+            user: user.to_string(),
+            command: shlex::try_join(argv.iter().map(|a| a.as_str())).unwrap_or_default(),
+            argv,
+            mode: String::from("argv"),
+            executor: executor.to_string(),
+            cwd: None,
+            env_clear: false,
+            add_time,
+            status: JobStatus::Waiting,
+            start_time: -1,
+            finish_time: -1,
+            stdout_log: None,
+            stderr_log: None,
+            exit_code: None,
+            signal: None,
+            owner_pid: None,
+            owner_host: None,
+            owner_boot_id: None,
+            requeue_on_crash: false,
+            pid: None,
+            pgid: None,
+            timeout: None,
+            retries: 0,
+            retry_delay: 0,
+            retry_backoff: 1.0,
+            attempt: 0,
+            retry_at: None,
+            priority: 0,
+            position: 0.0,
+            not_before: None,
+            queue: String::from("default"),
+            user_time: None,
+            system_time: None,
+            max_rss: None,
+            kill_requested: None,
+        }
+    }
+}
+
+/// The schema migrations, after `MIGRATIONS[i]` the `user_version` of the database is `i + 1`.
+static MIGRATIONS: [fn(&Connection) -> Result<()>; 13] = [
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+    migrate_v4,
+    migrate_v5,
+    migrate_v6,
+    migrate_v7,
+    migrate_v8,
+    migrate_v9,
+    migrate_v10,
+    migrate_v11,
+    migrate_v12,
+    migrate_v13,
+];
+
+/// The tables before the versioned migrations, older databases may miss some columns.
+fn migrate_v1(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commands (
+                id          INTEGER PRIMARY KEY,
+                user        TEXT NOT NULL,
+                command     TEXT NOT NULL,
+                executor    TEXT NOT NULL,
+                add_time    INTEGER,
+                status      INTEGER,
+                start_time  INTEGER,
+                finish_time INTEGER
+            )",
+        (), // empty list of parameters.
+    )?;
+    SqliteDB::add_column(conn, "commands", "stdout_log", "TEXT")?;
+    SqliteDB::add_column(conn, "commands", "stderr_log", "TEXT")?;
+    SqliteDB::add_column(conn, "commands", "exit_code", "INTEGER")?;
+    SqliteDB::add_column(conn, "commands", "signal", "INTEGER")?;
+    SqliteDB::add_column(conn, "commands", "argv", "TEXT")?;
+    SqliteDB::add_column(conn, "commands", "mode", "TEXT NOT NULL DEFAULT 'argv'")?;
+    SqliteDB::add_column(conn, "commands", "cwd", "TEXT")?;
+    SqliteDB::add_column(conn, "commands", "env_clear", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS environments (
+                job_id      INTEGER NOT NULL,
+                key         TEXT NOT NULL,
+                value       TEXT NOT NULL,
+                PRIMARY KEY (job_id, key)
+            )",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+                key         TEXT PRIMARY KEY,
+                value       TEXT NOT NULL
+            )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// The executor which runs the job, used to find the jobs left by a crashed executor.
+fn migrate_v2(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN owner_pid INTEGER;
+         ALTER TABLE commands ADD COLUMN owner_host TEXT;
+         ALTER TABLE commands ADD COLUMN owner_boot_id TEXT;
+         ALTER TABLE commands ADD COLUMN requeue_on_crash INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// The running executors, they update the heartbeat regularly.
+fn migrate_v3(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS executors (
+                pid         INTEGER NOT NULL,
+                host        TEXT NOT NULL,
+                boot_id     TEXT NOT NULL,
+                slots       INTEGER NOT NULL,
+                start_time  INTEGER NOT NULL,
+                heartbeat   INTEGER NOT NULL,
+                PRIMARY KEY (host, pid)
+            )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// The process of the running job, used to send signals from the cli.
+fn migrate_v4(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN pid INTEGER;
+         ALTER TABLE commands ADD COLUMN pgid INTEGER;",
+    )
+}
+
+/// Wall-clock timeout of the job.
+fn migrate_v5(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE commands ADD COLUMN timeout INTEGER;")
+}
+
+/// Retry the failed jobs, every run of a job is saved in the attempts table.
+fn migrate_v6(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN retries INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE commands ADD COLUMN retry_delay INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE commands ADD COLUMN retry_backoff REAL NOT NULL DEFAULT 1;
+         ALTER TABLE commands ADD COLUMN attempt INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE commands ADD COLUMN retry_at INTEGER;
+         CREATE TABLE IF NOT EXISTS attempts (
+                id          INTEGER PRIMARY KEY,
+                job_id      INTEGER NOT NULL,
+                attempt     INTEGER NOT NULL,
+                status      INTEGER NOT NULL,
+                start_time  INTEGER NOT NULL,
+                finish_time INTEGER NOT NULL,
+                exit_code   INTEGER,
+                signal      INTEGER,
+                stdout_log  TEXT,
+                stderr_log  TEXT
+            );",
+    )
+}
+
+/// The job runs only after the jobs it depends on, kind is ok, any or fail.
+fn migrate_v7(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS dependencies (
+                job_id      INTEGER NOT NULL,
+                depends_on  INTEGER NOT NULL,
+                kind        TEXT NOT NULL,
+                PRIMARY KEY (job_id, depends_on)
+            );",
+    )
+}
+
+/// Order the jobs by priority and position, the id of a job never changes.
+fn migrate_v8(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE commands ADD COLUMN position REAL NOT NULL DEFAULT 0;
+         UPDATE commands SET position=id;",
+    )
+}
+
+/// The job does not start before `not_before`.
+fn migrate_v9(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE commands ADD COLUMN not_before INTEGER;")
+}
+
+/// The recurring jobs, the executor adds a job from the schedule every time it fires.
+fn migrate_v10(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schedules (
+                id          INTEGER PRIMARY KEY,
+                expr        TEXT NOT NULL,
+                user        TEXT NOT NULL,
+                command     TEXT NOT NULL,
+                argv        TEXT NOT NULL,
+                mode        TEXT NOT NULL,
+                executor    TEXT NOT NULL,
+                cwd         TEXT,
+                missed      TEXT NOT NULL,
+                next_run    INTEGER,
+                add_time    INTEGER NOT NULL
+            );",
+    )
+}
+
+/// Every job belongs to a named queue, an executor serves one or more queues.
+fn migrate_v11(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN queue TEXT NOT NULL DEFAULT 'default';
+         ALTER TABLE schedules ADD COLUMN queue TEXT NOT NULL DEFAULT 'default';
+         ALTER TABLE executors ADD COLUMN queues TEXT NOT NULL DEFAULT 'default';",
+    )
+}
+
+/// Resource usage of the last run of the job.
+fn migrate_v12(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE commands ADD COLUMN user_time REAL;
+         ALTER TABLE commands ADD COLUMN system_time REAL;
+         ALTER TABLE commands ADD COLUMN max_rss INTEGER;",
+    )
+}
+
+/// `lucq kill` and the timeout only request the kill, the status is written after the reap.
+fn migrate_v13(conn: &Connection) -> Result<()> {
+    conn.execute_batch("ALTER TABLE commands ADD COLUMN kill_requested INTEGER;")
+}
+
+/// The job will not run anymore, whatever the result.
+static DONE_STATUSES: [JobStatus; 7] = [
+    JobStatus::Finished,
+    JobStatus::Error,
+    JobStatus::Cancelled,
+    JobStatus::Killed,
+    JobStatus::TimedOut,
+    JobStatus::Skipped,
+    JobStatus::Interrupted,
+];
+/// The job ran and failed, what `--after-fail` waits for.
+static FAILED_STATUSES: [JobStatus; 4] = [
+    JobStatus::Error,
+    JobStatus::Killed,
+    JobStatus::TimedOut,
+    JobStatus::Interrupted,
+];
+
+/// The statuses as a SQL list, example: (1, 2, 3).
+fn status_list(statuses: &[JobStatus]) -> String {
+    let numbers: Vec<String> = statuses.iter().map(|s| (*s as i64).to_string()).collect();
+    format!("({})", numbers.join(", "))
+}
+
+/// The dependency `d` on the job `p` is satisfied:
+/// ok needs Finished, fail needs a failed status, any needs the job is done.
+fn dependency_satisfied() -> String {
+    format!(
+        "(d.kind='ok' AND p.status={})
+    OR (d.kind='fail' AND p.status IN {})
+    OR (d.kind='any' AND p.status IN {})",
+        JobStatus::Finished as i64,
+        status_list(&FAILED_STATUSES),
+        status_list(&DONE_STATUSES)
+    )
+}
+/// The job `p` will not run anymore.
+fn dependency_done() -> String {
+    format!("p.status IN {}", status_list(&DONE_STATUSES))
+}
+
+/// Where `lucq move` puts the job.
+pub enum MoveTo {
+    Before(i32),
+    After(i32),
+    Top,
+    Bottom,
+}
+
+/// One run of a job.
+#[derive(Debug, Serialize)]
+pub struct Attempts {
+    pub job_id: i32,
+    pub attempt: i32, // start from 1
+    pub status: JobStatus,
+    #[serde(serialize_with = "rfc3339")]
     pub start_time: i64,
+    #[serde(serialize_with = "rfc3339")]
     pub finish_time: i64,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub stdout_log: Option<String>,
+    pub stderr_log: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Executors {
+    pub pid: i32,
+    pub host: String,
+    pub boot_id: String,
+    pub slots: i32,
+    pub start_time: i64,
+    pub heartbeat: i64, // UTC timestamp of the last heartbeat
+    pub queues: String, // the served queues in priority order, example: fast,default
+}
+
+/// A recurring job added by `lucq cron add`.
+#[derive(Debug)]
+pub struct Schedules {
+    pub id: i32,
+    pub expr: String, // cron expression, example: 0 3 * * *
+    pub user: String,
+    pub command: String,
+    pub argv: Vec<String>,
+    pub mode: String,
+    pub executor: String,
+    pub cwd: Option<String>,
+    pub missed: String, // what to do with the missed fires: skip, once or all
+    pub queue: String,
+    pub next_run: Option<i64>, // UTC timestamp, `None` if it never fires again
+    pub add_time: i64,
 }
 
 pub struct SqliteDB {
@@ -27,111 +479,298 @@ impl SqliteDB {
         // println!("{}", sqlite_file_path);
         let conn = Connection::open(sqlite_file_path)?;
         // let conn = Connection::open_in_memory()?;
+        // the executors and the cli may write at the same time
+        conn.busy_timeout(Duration::from_secs(10))?;
+        SqliteDB::migrate(&conn)?;
+        Ok(SqliteDB { conn })
+    }
+    /// Upgrade the database in place, `PRAGMA user_version` is the number of migrations done.
+    fn migrate(conn: &Connection) -> Result<()> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = conn.unchecked_transaction()?;
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+    /// Add a column to an existing table if an older database does not have it yet.
+    fn add_column(conn: &Connection, table: &str, column: &str, column_type: &str) -> Result<()> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        for name in names {
+            if name? == column {
+                return Ok(());
+            }
+        }
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS commands (
-                    id          INTEGER PRIMARY KEY,
-                    user        TEXT NOT NULL,
-                    command     TEXT NOT NULL,
-                    executor    TEXT NOT NULL,
-                    add_time    INTEGER,
-                    status      INTEGER,
-                    start_time  INTEGER,
-                    finish_time INTEGER
-                )",
-            (), // empty list of parameters.
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, column_type
+            ),
+            (),
         )?;
-        Ok(SqliteDB { conn })
+        Ok(())
     }
-    pub fn insert(&self, user: &str, command: &str, executor: &str, add_time: i64) -> Result<()> {
-        let cm = Commands {
-            id: 0,
-            user: user.to_string(),
-            command: command.to_string(),
-            executor: executor.to_string(),
-            add_time,
-            status: 0,
-            start_time: -1,
-            finish_time: -1,
+    fn row_to_commands(row: &Row) -> Result<Commands> {
+        let command: String = row.get(2)?;
+        // rows added before the argv column only have the command string
+        let argv = match row.get::<_, Option<String>>(12)? {
+            Some(argv) => serde_json::from_str(&argv)
+                .map_err(|e| FromSqlConversionFailure(12, Type::Text, Box::new(e)))?,
+            None => command.split(" ").map(|c| c.to_string()).collect(),
         };
+        Ok(Commands {
+            id: row.get(0)?,
+            user: row.get(1)?,
+            command,
+            argv,
+            mode: row.get(13)?,
+            executor: row.get(3)?,
+            cwd: row.get(14)?,
+            env_clear: row.get(15)?,
+            add_time: row.get(4)?,
+            status: row.get(5)?,
+            start_time: row.get(6)?,
+            finish_time: row.get(7)?,
+            stdout_log: row.get(8)?,
+            stderr_log: row.get(9)?,
+            exit_code: row.get(10)?,
+            signal: row.get(11)?,
+            owner_pid: row.get(16)?,
+            owner_host: row.get(17)?,
+            owner_boot_id: row.get(18)?,
+            requeue_on_crash: row.get(19)?,
+            pid: row.get(20)?,
+            pgid: row.get(21)?,
+            timeout: row.get(22)?,
+            retries: row.get(23)?,
+            retry_delay: row.get(24)?,
+            retry_backoff: row.get(25)?,
+            attempt: row.get(26)?,
+            retry_at: row.get(27)?,
+            priority: row.get(28)?,
+            position: row.get(29)?,
+            not_before: row.get(30)?,
+            queue: row.get(31)?,
+            user_time: row.get(32)?,
+            system_time: row.get(33)?,
+            max_rss: row.get(34)?,
+            kill_requested: row.get(35)?,
+        })
+    }
+    pub fn insert(&self, cm: &Commands) -> Result<i32> {
+        let argv = serde_json::to_string(&cm.argv).unwrap();
         self.conn.execute(
-            "INSERT INTO commands (user, command, executor, add_time, status, start_time, finish_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            (&cm.user, &cm.command, &cm.executor, &cm.add_time, &cm.status, &cm.start_time, &cm.finish_time),
+            "INSERT INTO commands (user, command, argv, mode, executor, cwd, env_clear, requeue_on_crash, timeout, retries, retry_delay, retry_backoff, priority, position, not_before, queue, add_time, status, start_time, finish_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            params![&cm.user, &cm.command, &argv, &cm.mode, &cm.executor, &cm.cwd, &cm.env_clear, &cm.requeue_on_crash, &cm.timeout, &cm.retries, &cm.retry_delay, &cm.retry_backoff, &cm.priority, &cm.position, &cm.not_before, &cm.queue, &cm.add_time, &cm.status, &cm.start_time, &cm.finish_time],
         )?;
-        Ok(())
+        Ok(self.conn.last_insert_rowid() as i32)
+    }
+    /// Take the write lock now, the other executors and cli wait until the commit,
+    /// rolled back if dropped without commit.
+    pub fn transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)
     }
-    pub fn insert_with_id(
+    /// Position after the last job.
+    pub fn next_position(&self) -> Result<f64> {
+        self.conn.query_row(
+            "SELECT COALESCE(MAX(position), 0) + 1 FROM commands",
+            [],
+            |row| row.get(0),
+        )
+    }
+    /// Position between the job and the job before (or after) it, `None` if the job not exists,
+    /// the moving job is not counted as a neighbor.
+    pub fn position_next_to(
         &self,
         id: i32,
-        user: &str,
-        command: &str,
-        executor: &str,
-        add_time: i64,
-    ) -> Result<()> {
-        let cm = Commands {
-            id,
-            user: user.to_string(),
-            command: command.to_string(),
-            executor: executor.to_string(),
-            add_time,
-            status: 0,
-            start_time: -1,
-            finish_time: -1,
+        before: bool,
+        moving: Option<i32>,
+    ) -> Result<Option<f64>> {
+        let position: f64 = match self.select_by_id(id)? {
+            Some(c) => c.position,
+            None => return Ok(None),
         };
-        self.conn.execute(
-            "INSERT INTO commands (id, user, command, executor, add_time, status, start_time, finish_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            (&cm.id, &cm.user, &cm.command, &cm.executor, &cm.add_time, &cm.status, &cm.start_time, &cm.finish_time),
+        let s = if before {
+            "SELECT MAX(position) FROM commands WHERE position<?1 AND id IS NOT ?2"
+        } else {
+            "SELECT MIN(position) FROM commands WHERE position>?1 AND id IS NOT ?2"
+        };
+        let neighbor: Option<f64> = self
+            .conn
+            .query_row(s, params![position, moving], |row| row.get(0))?;
+        let new_position = match neighbor {
+            Some(neighbor) => (position + neighbor) / 2.0,
+            None if before => position - 1.0,
+            None => position + 1.0,
+        };
+        if Some(new_position) == neighbor || new_position == position {
+            // no f64 left between the two jobs, make room and try again
+            self.renumber_positions()?;
+            return self.position_next_to(id, before, moving);
+        }
+        Ok(Some(new_position))
+    }
+    /// Move the job to a new position, only the position changes, not the id,
+    /// false if the job or the target job not exists.
+    pub fn move_job(&self, id: i32, to: MoveTo) -> Result<bool> {
+        let tx = self.transaction()?;
+        if self.select_by_id(id)?.is_none() {
+            return Ok(false);
+        }
+        let position = match to {
+            MoveTo::Before(target) => self.position_next_to(target, true, Some(id))?,
+            MoveTo::After(target) => self.position_next_to(target, false, Some(id))?,
+            MoveTo::Top => Some(tx.query_row(
+                "SELECT COALESCE(MIN(position), 1) - 1 FROM commands WHERE id<>?1",
+                [id],
+                |row| row.get(0),
+            )?),
+            MoveTo::Bottom => Some(tx.query_row(
+                "SELECT COALESCE(MAX(position), 0) + 1 FROM commands WHERE id<>?1",
+                [id],
+                |row| row.get(0),
+            )?),
+        };
+        let position: f64 = match position {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+        tx.execute(
+            "UPDATE commands SET position=?1 WHERE id=?2",
+            params![position, id],
         )?;
+        tx.commit()?;
+        Ok(true)
+    }
+    /// Renumber the positions to 1, 2, 3... and keep the order,
+    /// the positions get closer after every `--before` and `--after`.
+    pub fn align_positions(&self) -> Result<()> {
+        let tx = self.transaction()?;
+        self.renumber_positions()?;
+        tx.commit()?;
+        Ok(())
+    }
+    /// Renumber the positions without a transaction of its own, the caller holds one.
+    fn renumber_positions(&self) -> Result<()> {
+        let ids = self
+            .conn
+            .prepare("SELECT id FROM commands ORDER BY position ASC, id ASC")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<i32>>>()?;
+        for (i, id) in ids.iter().enumerate() {
+            self.conn.execute(
+                "UPDATE commands SET position=?1 WHERE id=?2",
+                params![i as f64 + 1.0, id],
+            )?;
+        }
         Ok(())
     }
     pub fn remove_by_id(&self, id: i32) -> Result<()> {
         self.conn
             .execute(&format!("DELETE FROM commands WHERE id={}", id), ())?;
+        self.conn
+            .execute("DELETE FROM environments WHERE job_id=?1", [id])?;
+        self.conn
+            .execute("DELETE FROM attempts WHERE job_id=?1", [id])?;
+        // the jobs depend on it do not wait for it anymore
+        self.conn.execute(
+            "DELETE FROM dependencies WHERE job_id=?1 OR depends_on=?1",
+            [id],
+        )?;
         Ok(())
     }
-    pub fn select_all(&self) -> Result<Vec<Commands>> {
+    pub fn insert_attempt(&self, a: &Attempts) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO attempts (job_id, attempt, status, start_time, finish_time, exit_code, signal, stdout_log, stderr_log) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![a.job_id, a.attempt, a.status, a.start_time, a.finish_time, a.exit_code, a.signal, a.stdout_log, a.stderr_log],
+        )?;
+        Ok(())
+    }
+    pub fn select_attempts(&self, job_id: i32) -> Result<Vec<Attempts>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, user, command, executor, add_time, status, start_time, finish_time FROM commands ORDER BY id ASC",
+            "SELECT job_id, attempt, status, start_time, finish_time, exit_code, signal, stdout_log, stderr_log FROM attempts WHERE job_id=?1 ORDER BY id ASC",
         )?;
-
-        let commands_iter = stmt.query_map([], |row| {
-            Ok(Commands {
-                id: row.get(0)?,
-                user: row.get(1)?,
-                command: row.get(2)?,
-                executor: row.get(3)?,
-                add_time: row.get(4)?,
-                status: row.get(5)?,
-                start_time: row.get(6)?,
-                finish_time: row.get(7)?,
+        let attempts_iter = stmt.query_map([job_id], |row| {
+            Ok(Attempts {
+                job_id: row.get(0)?,
+                attempt: row.get(1)?,
+                status: row.get(2)?,
+                start_time: row.get(3)?,
+                finish_time: row.get(4)?,
+                exit_code: row.get(5)?,
+                signal: row.get(6)?,
+                stdout_log: row.get(7)?,
+                stderr_log: row.get(8)?,
             })
         })?;
-
-        let mut ret: Vec<Commands> = Vec::new();
-        for command in commands_iter {
-            match command {
-                Ok(c) => ret.push(c),
-                Err(e) => return Err(e),
+        attempts_iter.collect()
+    }
+    pub fn insert_env(&self, job_id: i32, env: &[(String, String)]) -> Result<()> {
+        for (key, value) in env {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO environments (job_id, key, value) VALUES (?1, ?2, ?3)",
+                (job_id, key, value),
+            )?;
+        }
+        Ok(())
+    }
+    pub fn select_env(&self, job_id: i32) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT key, value FROM environments WHERE job_id=?1 ORDER BY key ASC")?;
+        let env_iter = stmt.query_map([job_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        env_iter.collect()
+    }
+    pub fn insert_dependencies(&self, job_id: i32, deps: &[(i32, String)]) -> Result<()> {
+        for (depends_on, kind) in deps {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO dependencies (job_id, depends_on, kind) VALUES (?1, ?2, ?3)",
+                params![job_id, depends_on, kind],
+            )?;
+        }
+        Ok(())
+    }
+    pub fn select_dependencies(&self, job_id: i32) -> Result<Vec<(i32, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT depends_on, kind FROM dependencies WHERE job_id=?1 ORDER BY depends_on ASC",
+        )?;
+        let deps_iter = stmt.query_map([job_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        deps_iter.collect()
+    }
+    /// Mark the waiting jobs as skipped if one of their dependencies is done but not satisfied,
+    /// repeat until no more job is skipped, so the jobs depend on a skipped job are skipped too.
+    pub fn skip_unsatisfied(&self) -> Result<Vec<i32>> {
+        let s = format!(
+            "UPDATE commands SET status=?1 WHERE status=?2
+             AND EXISTS (SELECT 1 FROM dependencies d JOIN commands p ON p.id=d.depends_on
+                         WHERE d.job_id=commands.id AND {} AND NOT ({}))
+             RETURNING id",
+            dependency_done(),
+            dependency_satisfied()
+        );
+        let mut skipped = Vec::new();
+        loop {
+            let mut stmt = self.conn.prepare(&s)?;
+            let ids = stmt
+                .query_map((JobStatus::Skipped, JobStatus::Waiting), |row| row.get(0))?
+                .collect::<Result<Vec<i32>>>()?;
+            if ids.is_empty() {
+                return Ok(skipped);
             }
+            skipped.extend(ids);
         }
-
-        Ok(ret)
     }
-    pub fn select_after(&self, id: i32) -> Result<Vec<Commands>> {
-        let s = format!("SELECT id, user, command, executor, add_time, status, start_time, finish_time FROM commands WHERE id>{} ORDER BY id ASC", id);
+    pub fn select_all(&self) -> Result<Vec<Commands>> {
+        let s = format!(
+            "SELECT {} FROM commands ORDER BY position ASC, id ASC",
+            COLUMNS
+        );
         let mut stmt = self.conn.prepare(&s)?;
 
-        let commands_iter = stmt.query_map([], |row| {
-            Ok(Commands {
-                id: row.get(0)?,
-                user: row.get(1)?,
-                command: row.get(2)?,
-                executor: row.get(3)?,
-                add_time: row.get(4)?,
-                status: row.get(5)?,
-                start_time: row.get(6)?,
-                finish_time: row.get(7)?,
-            })
-        })?;
+        let commands_iter = stmt.query_map([], SqliteDB::row_to_commands)?;
 
         let mut ret: Vec<Commands> = Vec::new();
         for command in commands_iter {
@@ -143,23 +782,24 @@ impl SqliteDB {
 
         Ok(ret)
     }
-    pub fn select_not_finish(&self) -> Result<Vec<Commands>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, user, command, executor, add_time, status, start_time, finish_time FROM commands WHERE status=0 ORDER BY id ASC LIMIT 1",
-        )?;
+    /// The next job to run in the queue, the jobs waiting for the retry delay, the start time or
+    /// for their dependencies are skipped.
+    pub fn select_not_finish(&self, queue: &str, now: i64) -> Result<Vec<Commands>> {
+        let s = format!(
+            "SELECT {} FROM commands WHERE status=?1 AND queue=?3 AND (retry_at IS NULL OR retry_at<=?2)
+             AND (not_before IS NULL OR not_before<=?2)
+             AND NOT EXISTS (SELECT 1 FROM dependencies d JOIN commands p ON p.id=d.depends_on
+                             WHERE d.job_id=commands.id AND NOT ({}))
+             ORDER BY priority DESC, position ASC, id ASC LIMIT 1",
+            COLUMNS,
+            dependency_satisfied()
+        );
+        let mut stmt = self.conn.prepare(&s)?;
 
-        let commands_iter = stmt.query_map([], |row| {
-            Ok(Commands {
-                id: row.get(0)?,
-                user: row.get(1)?,
-                command: row.get(2)?,
-                executor: row.get(3)?,
-                add_time: row.get(4)?,
-                status: row.get(5)?,
-                start_time: row.get(7)?,
-                finish_time: row.get(6)?,
-            })
-        })?;
+        let commands_iter = stmt.query_map(
+            params![JobStatus::Waiting, now, queue],
+            SqliteDB::row_to_commands,
+        )?;
 
         let mut ret: Vec<Commands> = Vec::new();
         for command in commands_iter {
@@ -172,21 +812,13 @@ impl SqliteDB {
         Ok(ret)
     }
     pub fn select_grep(&self, name: &str) -> Result<Vec<Commands>> {
-        let s = format!("SELECT id, user, command, executor, add_time, status, start_time, finish_time FROM commands WHERE command LIKE '%{}%' ORDER BY id ASC", name);
+        let s = format!(
+            "SELECT {} FROM commands WHERE command LIKE '%{}%' ORDER BY position ASC, id ASC",
+            COLUMNS, name
+        );
         let mut stmt = self.conn.prepare(&s)?;
 
-        let commands_iter = stmt.query_map([], |row| {
-            Ok(Commands {
-                id: row.get(0)?,
-                user: row.get(1)?,
-                command: row.get(2)?,
-                executor: row.get(3)?,
-                add_time: row.get(4)?,
-                status: row.get(5)?,
-                start_time: row.get(7)?,
-                finish_time: row.get(6)?,
-            })
-        })?;
+        let commands_iter = stmt.query_map([], SqliteDB::row_to_commands)?;
 
         let mut ret: Vec<Commands> = Vec::new();
         for command in commands_iter {
@@ -198,26 +830,157 @@ impl SqliteDB {
 
         Ok(ret)
     }
-    pub fn update_status_running(&self, id: i32) -> Result<()> {
-        let stmt = format!("UPDATE commands SET status=9 WHERE id={}", id);
-        self.conn.execute(&stmt, ())?;
+    pub fn update_status(&self, id: i32, status: JobStatus) -> Result<()> {
+        self.conn
+            .execute("UPDATE commands SET status=?1 WHERE id=?2", (status, id))?;
         Ok(())
     }
-    pub fn update_status_finish(&self, id: i32) -> Result<()> {
-        let stmt = format!("UPDATE commands SET status=1 WHERE id={}", id);
-        self.conn.execute(&stmt, ())?;
+    /// Switch between `Running` and `Paused`, false if the executor reaped the job
+    /// and wrote its final status in the meantime.
+    pub fn update_running_status(&self, id: i32, status: JobStatus) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE commands SET status=?1 WHERE id=?2 AND status IN (?3, ?4)",
+            (status, id, JobStatus::Running, JobStatus::Paused),
+        )?;
+        Ok(changed > 0)
+    }
+    /// The job is killed (`Killed`) or timed out (`TimedOut`), it is still running
+    /// until the executor reaps it, then `finish_job` writes the status.
+    pub fn request_kill(&self, id: i32, status: JobStatus) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET kill_requested=?1 WHERE id=?2",
+            (status, id),
+        )?;
         Ok(())
     }
-    pub fn update_status_error(&self, id: i32) -> Result<()> {
-        let stmt = format!("UPDATE commands SET status=2 WHERE id={}", id);
-        self.conn.execute(&stmt, ())?;
+    pub fn update_owner(&self, id: i32, pid: i32, host: &str, boot_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET owner_pid=?1, owner_host=?2, owner_boot_id=?3 WHERE id=?4",
+            (pid, host, boot_id, id),
+        )?;
         Ok(())
     }
-    pub fn update_status_cancel(&self, id: i32) -> Result<()> {
-        let stmt = format!("UPDATE commands SET status=3 WHERE id={}", id);
-        self.conn.execute(&stmt, ())?;
+    pub fn update_pid(&self, id: i32, pid: i32, pgid: i32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET pid=?1, pgid=?2 WHERE id=?3",
+            (pid, pgid, id),
+        )?;
         Ok(())
     }
+    /// Put the failed job back into the queue, it runs again after `retry_at`.
+    pub fn retry_later(&self, id: i32, retry_at: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET status=?1, retry_at=?2, start_time=-1, finish_time=-1, exit_code=NULL, signal=NULL, user_time=NULL, system_time=NULL, max_rss=NULL, kill_requested=NULL, pid=NULL, pgid=NULL, owner_pid=NULL, owner_host=NULL, owner_boot_id=NULL WHERE id=?3",
+            (JobStatus::Waiting, retry_at, id),
+        )?;
+        Ok(())
+    }
+    /// Put the job back into the queue as a new job.
+    pub fn requeue(&self, id: i32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET status=?1, start_time=-1, finish_time=-1, exit_code=NULL, signal=NULL, user_time=NULL, system_time=NULL, max_rss=NULL, kill_requested=NULL, pid=NULL, pgid=NULL, owner_pid=NULL, owner_host=NULL, owner_boot_id=NULL WHERE id=?2",
+            (JobStatus::Waiting, id),
+        )?;
+        Ok(())
+    }
+    /// Put the job back into the queue by the user, the retries start again.
+    pub fn retry(&self, id: i32) -> Result<()> {
+        self.requeue(id)?;
+        self.conn.execute(
+            "UPDATE commands SET attempt=0, retry_at=NULL WHERE id=?1",
+            [id],
+        )?;
+        Ok(())
+    }
+    /// The jobs skipped because of the job wait again, and the jobs skipped because of them.
+    pub fn unskip_dependents(&self, id: i32) -> Result<Vec<i32>> {
+        let mut stmt = self.conn.prepare(
+            "WITH RECURSIVE skipped(id) AS (
+                 SELECT d.job_id FROM dependencies d JOIN commands c ON c.id=d.job_id
+                 WHERE d.depends_on=?1 AND c.status=?2
+                 UNION
+                 SELECT d.job_id FROM dependencies d JOIN skipped s ON d.depends_on=s.id
+                 JOIN commands c ON c.id=d.job_id WHERE c.status=?2
+             )
+             UPDATE commands SET status=?3 WHERE id IN skipped RETURNING id",
+        )?;
+        let ids = stmt
+            .query_map(params![id, JobStatus::Skipped, JobStatus::Waiting], |row| {
+                row.get(0)
+            })?
+            .collect();
+        ids
+    }
+    /// Take the next waiting job for the executor, the job is changed to running
+    /// in the same transaction, so two executors can never start the same job.
+    pub fn claim_next(
+        &self,
+        queue: &str,
+        pid: i32,
+        host: &str,
+        boot_id: &str,
+        start_time: i64,
+    ) -> Result<Option<Commands>> {
+        let tx = self.transaction()?;
+        let next = match self
+            .select_not_finish(queue, start_time)?
+            .into_iter()
+            .next()
+        {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let s = format!(
+            "UPDATE commands SET status=?1, start_time=?2, owner_pid=?3, owner_host=?4, owner_boot_id=?5, attempt=attempt+1, retry_at=NULL WHERE id=?6 AND status=?7 RETURNING {}",
+            COLUMNS
+        );
+        let claimed = tx
+            .query_row(
+                &s,
+                (
+                    JobStatus::Running,
+                    start_time,
+                    pid,
+                    host,
+                    boot_id,
+                    next.id,
+                    JobStatus::Waiting,
+                ),
+                SqliteDB::row_to_commands,
+            )
+            .optional()?;
+        tx.commit()?;
+        Ok(claimed)
+    }
+    /// Number of the running (or paused) jobs in the queue, whatever executor runs them.
+    pub fn count_running(&self, queue: &str) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM commands WHERE queue=?1 AND status IN (?2, ?3)",
+            params![queue, JobStatus::Running, JobStatus::Paused],
+            |row| row.get(0),
+        )
+    }
+    /// Names of all the queues which have jobs.
+    pub fn select_queues(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT queue FROM commands ORDER BY queue ASC")?;
+        let queues_iter = stmt.query_map([], |row| row.get(0))?;
+        queues_iter.collect()
+    }
+    /// The jobs which an executor has started and not reaped yet.
+    pub fn select_running(&self) -> Result<Vec<Commands>> {
+        let s = format!(
+            "SELECT {} FROM commands WHERE status IN (?1, ?2) ORDER BY id ASC",
+            COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&s)?;
+        let commands_iter = stmt.query_map(
+            [JobStatus::Running, JobStatus::Paused],
+            SqliteDB::row_to_commands,
+        )?;
+        commands_iter.collect()
+    }
     pub fn update_start_time(&self, id: i32, start_time: i64) -> Result<()> {
         let stmt = format!(
             "UPDATE commands SET start_time={} WHERE id={}",
@@ -234,64 +997,310 @@ impl SqliteDB {
         self.conn.execute(&stmt, ())?;
         Ok(())
     }
-    fn gen_move_vec(id_vec: &[i32]) -> (Vec<i32>, Vec<i32>) {
-        if id_vec.len() > 0 {
-            let mut id_vec_ret = Vec::new();
-            let id_vec_plus: Vec<i32> = id_vec.iter().map(|x| x + 1).collect();
-            for id in &id_vec_plus {
-                id_vec_ret.push(*id);
-                if !id_vec_plus.contains(&(id + 1)) {
-                    break;
-                }
-            }
-            (id_vec[..id_vec_ret.len()].to_vec(), id_vec_ret)
-        } else {
-            (vec![], vec![])
-        }
+    pub fn update_rusage(
+        &self,
+        id: i32,
+        user_time: f64,
+        system_time: f64,
+        max_rss: i64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET user_time=?1, system_time=?2, max_rss=?3 WHERE id=?4",
+            (user_time, system_time, max_rss, id),
+        )?;
+        Ok(())
     }
-    pub fn move_jobs(&self, id_vec: &[i32]) -> Result<()> {
-        let (id_vec_1, id_vec_2) = SqliteDB::gen_move_vec(id_vec);
-        for (id, new_id) in zip(id_vec_1.iter().rev(), id_vec_2.iter().rev()) {
-            let stmt = format!("UPDATE commands SET id={} WHERE id={}", new_id, id);
-            self.conn.execute(&stmt, ())?;
-        }
+    pub fn select_by_id(&self, id: i32) -> Result<Option<Commands>> {
+        let s = format!("SELECT {} FROM commands WHERE id={}", COLUMNS, id);
+        let mut stmt = self.conn.prepare(&s)?;
+        let mut commands_iter = stmt.query_map([], SqliteDB::row_to_commands)?;
+        commands_iter.next().transpose()
+    }
+    pub fn update_logs(&self, id: i32, stdout_log: &str, stderr_log: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET stdout_log=?1, stderr_log=?2 WHERE id=?3",
+            (stdout_log, stderr_log, id),
+        )?;
         Ok(())
     }
-    fn gen_align_vec(id_vec: &[i32]) -> (Vec<i32>, Vec<i32>) {
-        let mut new_id_vec: Vec<i32> = Vec::new();
-        for i in 0..id_vec.len() {
-            new_id_vec.push(i as i32 + 1);
-        }
-        let mut same_len = 0;
-        for i in 0..id_vec.len() {
-            if id_vec[i] != new_id_vec[i] {
-                same_len = i;
-                break;
-            }
-        }
-        (
-            id_vec[same_len..id_vec.len()].to_vec(),
-            new_id_vec[same_len..id_vec.len()].to_vec(),
-        )
+    pub fn update_exit_status(
+        &self,
+        id: i32,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE commands SET exit_code=?1, signal=?2 WHERE id=?3",
+            (exit_code, signal, id),
+        )?;
+        Ok(())
     }
-    pub fn align_id(&self, id_vec: &[i32]) -> Result<()> {
-        let (id_vec_1, id_vec_2) = SqliteDB::gen_align_vec(id_vec);
-        for (id, new_id) in zip(id_vec_1, id_vec_2) {
-            let stmt = format!("UPDATE commands SET id={} WHERE id={}", new_id, id);
-            self.conn.execute(&stmt, ())?;
-        }
+    pub fn register_executor(&self, e: &Executors) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO executors (pid, host, boot_id, slots, start_time, heartbeat, queues) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (e.pid, &e.host, &e.boot_id, e.slots, e.start_time, e.heartbeat, &e.queues),
+        )?;
         Ok(())
     }
+    pub fn update_heartbeat(&self, pid: i32, host: &str, heartbeat: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE executors SET heartbeat=?1 WHERE pid=?2 AND host=?3",
+            (heartbeat, pid, host),
+        )?;
+        Ok(())
+    }
+    /// Remove the executors which have not updated the heartbeat since `before`.
+    pub fn remove_stale_executors(&self, before: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM executors WHERE heartbeat<?1", [before])?;
+        Ok(())
+    }
+    pub fn select_executors(&self) -> Result<Vec<Executors>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT pid, host, boot_id, slots, start_time, heartbeat, queues FROM executors ORDER BY start_time ASC",
+        )?;
+        let executors_iter = stmt.query_map([], |row| {
+            Ok(Executors {
+                pid: row.get(0)?,
+                host: row.get(1)?,
+                boot_id: row.get(2)?,
+                slots: row.get(3)?,
+                start_time: row.get(4)?,
+                heartbeat: row.get(5)?,
+                queues: row.get(6)?,
+            })
+        })?;
+        executors_iter.collect()
+    }
+    pub fn insert_schedule(&self, s: &Schedules) -> Result<i32> {
+        let argv = serde_json::to_string(&s.argv).unwrap();
+        self.conn.execute(
+            "INSERT INTO schedules (expr, user, command, argv, mode, executor, cwd, missed, queue, next_run, add_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![&s.expr, &s.user, &s.command, &argv, &s.mode, &s.executor, &s.cwd, &s.missed, &s.queue, &s.next_run, &s.add_time],
+        )?;
+        Ok(self.conn.last_insert_rowid() as i32)
+    }
+    pub fn select_schedules(&self) -> Result<Vec<Schedules>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, expr, user, command, argv, mode, executor, cwd, missed, queue, next_run, add_time FROM schedules ORDER BY id ASC",
+        )?;
+        let schedules_iter = stmt.query_map([], |row| {
+            let argv: String = row.get(4)?;
+            Ok(Schedules {
+                id: row.get(0)?,
+                expr: row.get(1)?,
+                user: row.get(2)?,
+                command: row.get(3)?,
+                argv: serde_json::from_str(&argv).unwrap_or_default(),
+                mode: row.get(5)?,
+                executor: row.get(6)?,
+                cwd: row.get(7)?,
+                missed: row.get(8)?,
+                queue: row.get(9)?,
+                next_run: row.get(10)?,
+                add_time: row.get(11)?,
+            })
+        })?;
+        schedules_iter.collect()
+    }
+    /// False if the schedule not exists.
+    pub fn remove_schedule(&self, id: i32) -> Result<bool> {
+        let n = self
+            .conn
+            .execute("DELETE FROM schedules WHERE id=?1", [id])?;
+        Ok(n > 0)
+    }
+    pub fn update_next_run(&self, id: i32, next_run: Option<i64>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE schedules SET next_run=?1 WHERE id=?2",
+            (next_run, id),
+        )?;
+        Ok(())
+    }
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT value FROM settings WHERE key=?1")?;
+        let mut values = stmt.query_map([key], |row| row.get(0))?;
+        values.next().transpose()
+    }
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value=?2",
+            (key, value),
+        )?;
+        Ok(())
+    }
+    pub fn remove_setting(&self, key: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM settings WHERE key=?1", [key])?;
+        Ok(())
+    }
+    pub fn select_settings(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT key, value FROM settings ORDER BY key ASC")?;
+        let settings_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        settings_iter.collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    impl SqliteDB {
+        fn open_in_memory() -> Result<SqliteDB> {
+            let conn = Connection::open_in_memory()?;
+            SqliteDB::migrate(&conn)?;
+            Ok(SqliteDB { conn })
+        }
+    }
+    #[test]
+    fn test_dispatch_order() {
+        let db = SqliteDB::open_in_memory().unwrap();
+        let add = |name: &str, priority: i32, position: f64| {
+            let mut cm = Commands::new("riko", vec![name.to_string()], "null", 0);
+            cm.priority = priority;
+            cm.position = position;
+            db.insert(&cm).unwrap()
+        };
+        let a = add("a", 0, db.next_position().unwrap());
+        let b = add("b", 0, db.next_position().unwrap());
+        let c = add("c", 0, db.position_next_to(a, true, None).unwrap().unwrap());
+        let d = add(
+            "d",
+            -10,
+            db.position_next_to(c, true, None).unwrap().unwrap(),
+        );
+        let e = add("e", 10, db.next_position().unwrap());
+        let f = add(
+            "f",
+            0,
+            db.position_next_to(a, false, None).unwrap().unwrap(),
+        );
+        let mut order = Vec::new();
+        while let Some(next) = db
+            .select_not_finish("default", 0)
+            .unwrap()
+            .into_iter()
+            .next()
+        {
+            db.update_status(next.id, JobStatus::Finished).unwrap();
+            order.push(next.id);
+        }
+        assert_eq!(order, vec![e, c, a, f, b, d]);
+        // the jobs of the other queues are not taken
+        let mut cm = Commands::new("riko", vec![String::from("g")], "null", 0);
+        cm.queue = String::from("fast");
+        let g = db.insert(&cm).unwrap();
+        assert!(db.select_not_finish("default", 0).unwrap().is_empty());
+        assert_eq!(db.select_not_finish("fast", 0).unwrap()[0].id, g);
+        // the ids never change
+        assert_eq!(vec![a, b, c, d, e, f, g], vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+    #[test]
+    fn test_move_job() {
+        let db = SqliteDB::open_in_memory().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            let mut cm = Commands::new("riko", vec![name.to_string()], "null", 0);
+            cm.position = db.next_position().unwrap();
+            db.insert(&cm).unwrap();
+        }
+        let order =
+            |db: &SqliteDB| -> Vec<i32> { db.select_all().unwrap().iter().map(|c| c.id).collect() };
+        assert!(db.move_job(4, MoveTo::Before(2)).unwrap());
+        assert_eq!(order(&db), vec![1, 4, 2, 3]);
+        assert!(db.move_job(1, MoveTo::After(2)).unwrap());
+        assert_eq!(order(&db), vec![4, 2, 1, 3]);
+        assert!(db.move_job(3, MoveTo::Top).unwrap());
+        assert_eq!(order(&db), vec![3, 4, 2, 1]);
+        assert!(db.move_job(3, MoveTo::Bottom).unwrap());
+        assert_eq!(order(&db), vec![4, 2, 1, 3]);
+        // moving next to itself changes nothing
+        assert!(db.move_job(2, MoveTo::After(2)).unwrap());
+        assert_eq!(order(&db), vec![4, 2, 1, 3]);
+        assert!(!db.move_job(9, MoveTo::Top).unwrap());
+        assert!(!db.move_job(1, MoveTo::Before(9)).unwrap());
+        db.align_positions().unwrap();
+        let positions: Vec<f64> = db
+            .select_all()
+            .unwrap()
+            .iter()
+            .map(|c| c.position)
+            .collect();
+        assert_eq!(positions, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(order(&db), vec![4, 2, 1, 3]);
+    }
+    #[test]
+    fn test_dependencies() {
+        let db = SqliteDB::open_in_memory().unwrap();
+        let add = |name: &str, deps: &[(i32, String)]| {
+            let mut cm = Commands::new("riko", vec![name.to_string()], "null", 0);
+            cm.position = db.next_position().unwrap();
+            let id = db.insert(&cm).unwrap();
+            db.insert_dependencies(id, deps).unwrap();
+            id
+        };
+        let a = add("a", &[]);
+        let ok = add("ok", &[(a, String::from("ok"))]);
+        let fail = add("fail", &[(a, String::from("fail"))]);
+        let any = add("any", &[(a, String::from("any"))]);
+        let after_ok = add("after_ok", &[(ok, String::from("ok"))]);
+        let next = |db: &SqliteDB| -> Vec<i32> {
+            db.select_not_finish("default", 0)
+                .unwrap()
+                .iter()
+                .map(|c| c.id)
+                .collect()
+        };
+        assert_eq!(next(&db), vec![a]);
+        db.update_status(a, JobStatus::TimedOut).unwrap();
+        assert_eq!(db.skip_unsatisfied().unwrap(), vec![ok, after_ok]);
+        assert_eq!(next(&db), vec![fail]);
+        db.update_status(fail, JobStatus::Finished).unwrap();
+        assert_eq!(next(&db), vec![any]);
+    }
     #[test]
-    fn test_move_jobs() {
-        let id_vec = vec![7, 8, 10, 12];
-        let (id_vec_1, id_vec_2) = SqliteDB::gen_move_vec(&id_vec);
-        println!("{:?}", id_vec_1);
-        println!("{:?}", id_vec_2);
+    fn test_update_running_status() {
+        let db = SqliteDB::open_in_memory().unwrap();
+        let id = db
+            .insert(&Commands::new("riko", vec![String::from("a")], "null", 0))
+            .unwrap();
+        db.update_status(id, JobStatus::Running).unwrap();
+        assert!(db.update_running_status(id, JobStatus::Paused).unwrap());
+        assert!(db.update_running_status(id, JobStatus::Paused).unwrap());
+        // reaped by the executor before the pause was written
+        db.update_status(id, JobStatus::Finished).unwrap();
+        assert!(!db.update_running_status(id, JobStatus::Running).unwrap());
+        assert_eq!(
+            db.select_by_id(id).unwrap().unwrap().status,
+            JobStatus::Finished
+        );
+    }
+    #[test]
+    fn test_position_precision() {
+        let db = SqliteDB::open_in_memory().unwrap();
+        for name in ["a", "b"] {
+            let mut cm = Commands::new("riko", vec![name.to_string()], "null", 0);
+            cm.position = db.next_position().unwrap();
+            db.insert(&cm).unwrap();
+        }
+        // every job is added right after the first one, the gap halves every time
+        let mut expected = vec![1, 2];
+        for i in 0..100 {
+            let mut cm = Commands::new("riko", vec![i.to_string()], "null", 0);
+            cm.position = db.position_next_to(1, false, None).unwrap().unwrap();
+            let id = db.insert(&cm).unwrap();
+            expected.insert(1, id);
+        }
+        let order: Vec<i32> = db.select_all().unwrap().iter().map(|c| c.id).collect();
+        assert_eq!(order, expected);
+        // the same with moves, 2 and 3 take turns right after the first job
+        for i in 0..100 {
+            let id = if i % 2 == 0 { 2 } else { 3 };
+            assert!(db.move_job(id, MoveTo::After(1)).unwrap());
+        }
+        let order: Vec<i32> = db.select_all().unwrap().iter().map(|c| c.id).collect();
+        assert_eq!(order[..3], [1, 3, 2]);
     }
 }
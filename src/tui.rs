@@ -0,0 +1,375 @@
+//! `lucq tui`, watch and manage the queue in the terminal.
+use anyhow::Result;
+use chrono::Utc;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time;
+
+use crate::executor::{group_alive, signal_group};
+use crate::func::{
+    cancel_one, delete_one, format_duration, kill_one, move_one, pause_one, priority_name,
+    resume_one, retry_one, status_symbol,
+};
+use crate::sqlitedb::{Commands, JobStatus, MoveTo, SqliteDB};
+
+/// Reload the jobs from the database after this many milliseconds.
+const REFRESH_INTERVAL: u64 = 1000;
+/// Seconds to wait before SIGKILL, like `lucq kill`.
+const KILL_GRACE: i64 = 10;
+/// Only the end of the log is read.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+static PANES: [&str; 3] = ["Running", "Waiting", "Finished"];
+
+static HELP: &str = "q quit | tab pane | ↑↓ select | c cancel | x kill | p pause/resume | r retry | d delete | [ ] t b move | e stdout/stderr";
+
+struct App {
+    db: SqliteDB,
+    noemoji: bool,
+    panes: [Vec<Commands>; 3],
+    states: [ListState; 3],
+    focus: usize,
+    stderr: bool,
+    message: String,
+    confirm_delete: Option<i32>,
+    // jobs which got SIGTERM: (id, pgid, UTC timestamp to send SIGKILL)
+    killing: Vec<(i32, i32, i64)>,
+    // quit once the killed jobs are gone, SIGKILL is only sent while the tui is open
+    quitting: bool,
+}
+
+impl App {
+    fn new(noemoji: bool) -> Result<App> {
+        let mut app = App {
+            db: SqliteDB::new()?,
+            noemoji,
+            panes: [Vec::new(), Vec::new(), Vec::new()],
+            states: [
+                ListState::default(),
+                ListState::default(),
+                ListState::default(),
+            ],
+            focus: 0,
+            stderr: false,
+            message: String::from(HELP),
+            confirm_delete: None,
+            killing: Vec::new(),
+            quitting: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+    /// Read the jobs again and keep the selected job selected.
+    fn reload(&mut self) -> Result<()> {
+        let selected: Vec<Option<i32>> = (0..PANES.len()).map(|i| self.selected_in(i)).collect();
+        let mut panes = [Vec::new(), Vec::new(), Vec::new()];
+        for c in self.db.select_all()? {
+            let pane = match c.status {
+                JobStatus::Running | JobStatus::Paused => 0,
+                JobStatus::Waiting => 1,
+                _ => 2,
+            };
+            panes[pane].push(c);
+        }
+        // the waiting jobs in the order they start, the last finished job first
+        panes[1].sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then(a.position.total_cmp(&b.position))
+        });
+        panes[2].sort_by_key(|c| Reverse(c.finish_time));
+        self.panes = panes;
+        for (i, id) in selected.into_iter().enumerate() {
+            let index = match id {
+                Some(id) => self.panes[i].iter().position(|c| c.id == id),
+                None => None,
+            };
+            let index = match (index, self.panes[i].is_empty()) {
+                (_, true) => None,
+                (Some(index), false) => Some(index),
+                (None, false) => Some(
+                    self.states[i]
+                        .selected()
+                        .unwrap_or(0)
+                        .min(self.panes[i].len() - 1),
+                ),
+            };
+            self.states[i].select(index);
+        }
+        Ok(())
+    }
+    fn selected_in(&self, pane: usize) -> Option<i32> {
+        let index = self.states[pane].selected()?;
+        self.panes[pane].get(index).map(|c| c.id)
+    }
+    fn selected(&self) -> Option<&Commands> {
+        let index = self.states[self.focus].selected()?;
+        self.panes[self.focus].get(index)
+    }
+    fn select_next(&mut self, step: isize) {
+        let len = self.panes[self.focus].len();
+        if len == 0 {
+            return;
+        }
+        let index = self.states[self.focus].selected().unwrap_or(0) as isize + step;
+        let index = index.clamp(0, len as isize - 1) as usize;
+        self.states[self.focus].select(Some(index));
+    }
+    /// Move the selected waiting job before or after its neighbour.
+    fn move_selected(&mut self, id: i32, step: isize) -> Result<String> {
+        let pane = &self.panes[1];
+        let index = match pane.iter().position(|c| c.id == id) {
+            Some(index) => index as isize + step,
+            None => return Ok(format!("Job [{}] is not waiting!", id)),
+        };
+        if index < 0 || index >= pane.len() as isize {
+            return Ok(format!("Job [{}] can not move further", id));
+        }
+        let neighbour = pane[index as usize].id;
+        let to = if step < 0 {
+            MoveTo::Before(neighbour)
+        } else {
+            MoveTo::After(neighbour)
+        };
+        move_one(&self.db, id, to)
+    }
+    /// Send SIGKILL to the killed jobs which are still alive after the grace period.
+    fn kill_late(&mut self) {
+        let now = Utc::now().timestamp();
+        let mut message = None;
+        self.killing.retain(|(id, pgid, deadline)| {
+            if !group_alive(*pgid) {
+                return false;
+            }
+            if now >= *deadline {
+                signal_group(*pgid, libc::SIGKILL);
+                message = Some(format!("Send SIGKILL to job [{}]", id));
+                return false;
+            }
+            true
+        });
+        if let Some(message) = message {
+            self.message = message;
+        }
+    }
+    /// Quit now if no killed job is still alive, or wait for them, quit anyway the second time.
+    fn quit(&mut self) -> bool {
+        if self.killing.is_empty() || self.quitting {
+            return true;
+        }
+        self.quitting = true;
+        let ids: Vec<i32> = self.killing.iter().map(|(id, _, _)| *id).collect();
+        self.message = format!(
+            "Waiting for job(s) {:?} to stop before quit, q again to quit without SIGKILL",
+            ids
+        );
+        false
+    }
+    /// Handle one key, false to quit.
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<bool> {
+        if let Some(id) = self.confirm_delete.take() {
+            self.message = match code {
+                KeyCode::Char('y') => delete_one(&self.db, id)?,
+                _ => String::from(HELP),
+            };
+            self.reload()?;
+            return Ok(true);
+        }
+        let id = self.selected().map(|c| c.id);
+        let msg = match (code, id) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => return Ok(!self.quit()),
+            (KeyCode::Char('c'), _) if modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(!self.quit())
+            }
+            (KeyCode::Tab | KeyCode::Right, _) => {
+                self.focus = (self.focus + 1) % PANES.len();
+                None
+            }
+            (KeyCode::BackTab | KeyCode::Left, _) => {
+                self.focus = (self.focus + PANES.len() - 1) % PANES.len();
+                None
+            }
+            (KeyCode::Down | KeyCode::Char('j'), _) => {
+                self.select_next(1);
+                None
+            }
+            (KeyCode::Up | KeyCode::Char('k'), _) => {
+                self.select_next(-1);
+                None
+            }
+            (KeyCode::Char('e'), _) => {
+                self.stderr = !self.stderr;
+                None
+            }
+            (KeyCode::Char('c'), Some(id)) => Some(cancel_one(&self.db, id)?),
+            (KeyCode::Char('r'), Some(id)) => Some(retry_one(&self.db, id)?),
+            (KeyCode::Char('p'), Some(id)) => match self.selected().map(|c| c.status) {
+                Some(JobStatus::Paused) => Some(resume_one(&self.db, id)?),
+                _ => Some(pause_one(&self.db, id)?),
+            },
+            (KeyCode::Char('x'), Some(id)) => match kill_one(&self.db, id)? {
+                Ok(pgid) => {
+                    let deadline = Utc::now().timestamp() + KILL_GRACE;
+                    self.killing.push((id, pgid, deadline));
+                    Some(format!("Send SIGTERM to job [{}]", id))
+                }
+                Err(msg) => Some(msg),
+            },
+            (KeyCode::Char('d'), Some(id)) => {
+                self.confirm_delete = Some(id);
+                Some(format!("Delete job [{}] and its logs? [y/n]", id))
+            }
+            (KeyCode::Char('['), Some(id)) => Some(self.move_selected(id, -1)?),
+            (KeyCode::Char(']'), Some(id)) => Some(self.move_selected(id, 1)?),
+            (KeyCode::Char('t'), Some(id)) => Some(move_one(&self.db, id, MoveTo::Top)?),
+            (KeyCode::Char('b'), Some(id)) => Some(move_one(&self.db, id, MoveTo::Bottom)?),
+            _ => None,
+        };
+        if let Some(msg) = msg {
+            self.message = msg;
+            self.reload()?;
+        }
+        Ok(true)
+    }
+}
+
+/// The end of the log, at most `lines` lines.
+fn tail(path: &str, lines: usize) -> Vec<String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return vec![format!("{} not exists", path)],
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let _ = file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)));
+    let mut buf = Vec::new();
+    let _ = file.read_to_end(&mut buf);
+    let text = String::from_utf8_lossy(&buf);
+    let all: Vec<&str> = text.lines().collect();
+    all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// One job in a pane, example: 🥵 12 train.py -a 1 | 00:12:03
+fn job_item(c: &Commands, now: i64, noemoji: bool) -> ListItem<'static> {
+    let used = match (c.start_time, c.finish_time) {
+        (-1, _) => None,
+        (start, -1) => Some(now - start),
+        (start, finish) => Some(finish - start),
+    };
+    let mut line = format!(
+        "{} {} {}",
+        status_symbol(c.status, noemoji).trim(),
+        c.id,
+        c.command
+    );
+    if let Some(used) = used {
+        line = format!("{} | {}", line, format_duration(used));
+    }
+    if let (JobStatus::Running | JobStatus::Paused, Some(kill)) = (c.status, c.kill_requested) {
+        line = format!("{} | stopping({})", line, kill.name());
+    }
+    if c.status == JobStatus::Waiting && c.priority != 0 {
+        line = format!("{} | {}", line, priority_name(c.priority));
+    }
+    match c.not_before {
+        Some(not_before) if c.status == JobStatus::Waiting && not_before > now => {
+            line = format!("{} | in {}", line, format_duration(not_before - now));
+        }
+        _ => (),
+    }
+    ListItem::new(line)
+}
+
+fn draw_logs(frame: &mut Frame, app: &App, area: Rect) {
+    let (title, lines) = match app.selected() {
+        Some(c) => {
+            let log = if app.stderr {
+                &c.stderr_log
+            } else {
+                &c.stdout_log
+            };
+            let name = if app.stderr { "stderr" } else { "stdout" };
+            let lines = match log {
+                Some(log) => tail(log, area.height.saturating_sub(2) as usize),
+                None => vec![String::from("No log yet!")],
+            };
+            (format!(" [{}] {} ", c.id, name), lines)
+        }
+        None => (String::from(" Log "), Vec::new()),
+    };
+    let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let now = Utc::now().timestamp();
+    let [top, logs, status] = Layout::vertical([
+        Constraint::Percentage(55),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let panes = Layout::horizontal([Constraint::Ratio(1, 3); 3]).split(top);
+    for (i, name) in PANES.iter().enumerate() {
+        let items: Vec<ListItem> = app.panes[i]
+            .iter()
+            .map(|c| job_item(c, now, app.noemoji))
+            .collect();
+        let block = Block::bordered().title(format!(" {} ({}) ", name, app.panes[i].len()));
+        let block = if i == app.focus {
+            block.border_style(Style::new().cyan())
+        } else {
+            block
+        };
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, panes[i], &mut app.states[i]);
+    }
+    draw_logs(frame, app, logs);
+    frame.render_widget(Paragraph::new(app.message.as_str()), status);
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    let refresh = time::Duration::from_millis(REFRESH_INTERVAL);
+    let mut last_reload = time::Instant::now();
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+        let timeout = refresh.saturating_sub(last_reload.elapsed());
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.key(key.code, key.modifiers)? {
+                    return Ok(());
+                }
+            }
+        }
+        if last_reload.elapsed() >= refresh {
+            app.kill_late();
+            if app.quitting && app.killing.is_empty() {
+                return Ok(());
+            }
+            app.reload()?;
+            last_reload = time::Instant::now();
+        }
+    }
+}
+
+pub fn tui(noemoji: bool) -> Result<()> {
+    let mut app = App::new(noemoji)?;
+    let mut terminal = ratatui::init();
+    let ret = run(&mut terminal, &mut app);
+    ratatui::restore();
+    ret
+}